@@ -0,0 +1,41 @@
+#[cfg(feature = "ffi")]
+extern crate cbindgen;
+
+#[cfg(feature = "ffi")]
+use std::env;
+#[cfg(feature = "ffi")]
+use std::path::Path;
+
+fn main() {
+    println!("cargo:rerun-if-changed=build.rs");
+
+    #[cfg(feature = "ffi")]
+    write_header();
+}
+
+// generates the header for src/ffi.rs, which a test compares with the
+// checked in include/chip8.h
+#[cfg(feature = "ffi")]
+fn write_header() {
+    println!("cargo:rerun-if-changed=src/ffi.rs");
+
+    let config = cbindgen::Config {
+        usize_is_size_t: true,
+        ..Default::default()
+    };
+    cbindgen::Builder::new()
+        .with_config(config)
+        .with_src("src/ffi.rs")
+        .with_language(cbindgen::Language::C)
+        .with_include_guard("CHIP8_H")
+        .with_autogen_warning("/* Generated from src/ffi.rs by build.rs, don't edit */")
+        .with_sys_include("stdbool.h")
+        .with_sys_include("stddef.h")
+        .with_sys_include("stdint.h")
+        .with_no_includes()
+        .with_cpp_compat(true)
+        .with_documentation(true)
+        .generate()
+        .expect("couldn't generate the C header")
+        .write_to_file(Path::new(&env::var("OUT_DIR").unwrap()).join("chip8.h"));
+}
@@ -0,0 +1,95 @@
+#ifndef CHIP8_H
+#define CHIP8_H
+
+/* Generated from src/ffi.rs by build.rs, don't edit */
+
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+
+/**
+ * Bumped whenever a function changes in a way that breaks callers
+ */
+#define CHIP8_API_VERSION 1
+
+/**
+ * An emulator created by `chip8_new`
+ */
+typedef struct Chip8Handle Chip8Handle;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+uint32_t chip8_api_version(void);
+
+/**
+ * Creates an emulator with no program loaded. The seed drives the random
+ * numbers of Cxkk, the same seed and input always give the same run.
+ */
+struct Chip8Handle *chip8_new(uint64_t seed);
+
+/**
+ * Destroys an emulator from `chip8_new`
+ */
+void chip8_free(struct Chip8Handle *handle);
+
+/**
+ * Resets the emulator and loads a program of `len` bytes. Fails if the
+ * program doesn't fit in memory.
+ */
+bool chip8_load_rom(struct Chip8Handle *handle, const uint8_t *rom, size_t len);
+
+/**
+ * Runs one 60 hz frame
+ */
+void chip8_run_frame(struct Chip8Handle *handle);
+
+size_t chip8_width(const struct Chip8Handle *handle);
+
+size_t chip8_height(const struct Chip8Handle *handle);
+
+/**
+ * Returns the display as `chip8_width` times `chip8_height` bytes, row by
+ * row, 1 for a lit pixel and 0 otherwise. The pointer stays valid until
+ * the emulator is freed.
+ */
+const uint8_t *chip8_framebuffer(const struct Chip8Handle *handle);
+
+/**
+ * Presses or releases one of the keys 0 to F
+ */
+bool chip8_set_key(struct Chip8Handle *handle, uint8_t key, bool down);
+
+/**
+ * Sets all keys at once, bit n being key n
+ */
+void chip8_set_keys(struct Chip8Handle *handle, uint16_t keys);
+
+/**
+ * Whether the beeper is sounding
+ */
+bool chip8_sound_active(struct Chip8Handle *handle);
+
+/**
+ * Size of a save state in bytes
+ */
+size_t chip8_state_size(const struct Chip8Handle *handle);
+
+/**
+ * Writes a save state into `buffer`, which has to hold at least
+ * `chip8_state_size` bytes
+ */
+bool chip8_save_state(const struct Chip8Handle *handle, uint8_t *buffer, size_t len);
+
+/**
+ * Restores a save state from `chip8_save_state`. A broken state leaves
+ * the emulator as it was and returns false.
+ */
+bool chip8_load_state(struct Chip8Handle *handle, const uint8_t *state, size_t len);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* CHIP8_H */
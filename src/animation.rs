@@ -0,0 +1,249 @@
+use std::convert::TryFrom;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+use gif;
+use png;
+
+use display::Display;
+use palette::Palette;
+
+// GIF delays are in hundredths of a second, and most viewers slow down
+// frames shorter than this
+const GIF_MIN_DELAY: u32 = 2;
+// frames kept before recording stops, five minutes at 60 fps. frames are
+// held until the animation is saved, so this bounds the memory used
+const MAX_FRAMES: usize = 60 * 60 * 5;
+
+/// Collects the display once per frame and encodes the frames as an
+/// animated GIF or APNG running at 60 fps
+pub struct AnimationRecorder {
+    palette: Palette,
+    scale: usize,
+    dedup: bool,
+    size: (usize, usize),
+    // palette indices of every pixel and how many frames they were shown
+    frames: Vec<(Vec<u8>, u32)>,
+    full: bool
+}
+
+impl AnimationRecorder {
+    /// With `dedup` a frame that looks the same as the one before it
+    /// lengthens that frame instead of being stored again
+    pub fn new(palette: Palette, scale: usize, dedup: bool) -> Self {
+        AnimationRecorder {
+            palette,
+            scale,
+            dedup,
+            size: (0, 0),
+            frames: Vec::new(),
+            full: false
+        }
+    }
+
+    /// Adds the display as the next frame. Stops at a change of resolution
+    /// since every frame of an animation has the same size, and once
+    /// `MAX_FRAMES` frames are stored.
+    pub fn capture(&mut self, display: &Display) {
+        let size = (display.width(), display.height());
+        if self.frames.is_empty() {
+            self.size = size;
+        } else if size != self.size {
+            return;
+        }
+
+        let pixels: Vec<u8> = display.pixels().iter()
+            .flat_map(|row| row.iter().map(|&pixel| pixel as u8))
+            .collect();
+        if let Some(&mut (ref last, ref mut duration)) = self.frames.last_mut() {
+            if self.dedup && *last == pixels {
+                *duration += 1;
+                return;
+            }
+        }
+        if self.frames.len() == MAX_FRAMES {
+            if !self.full {
+                println!("WARNING: animation is full after {} frames, the rest isn't recorded", MAX_FRAMES);
+                self.full = true;
+            }
+            return;
+        }
+        self.frames.push((pixels, 1));
+    }
+
+    /// Number of frames stored, after deduplication
+    pub fn frame_count(&self) -> usize {
+        self.frames.len()
+    }
+
+    /// Saves as APNG for `.png` and `.apng` files, as GIF otherwise
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        if self.frames.is_empty() {
+            return Err("no frames were recorded".to_string());
+        }
+
+        let file = File::create(path)
+            .map_err(|why| format!("couldn't create {}: {}", path.display(), why))?;
+        let writer = BufWriter::new(file);
+        let result = match path.extension().and_then(|extension| extension.to_str()) {
+            Some("png") | Some("apng") => self.write_apng(writer),
+            _ => self.write_gif(writer)
+        };
+        result.map_err(|why| format!("couldn't write {}: {}", path.display(), why))
+    }
+
+    // the size of the scaled frames, if the format has room for it
+    fn scaled_size<T: TryFrom<usize>>(&self) -> Result<(T, T), String> {
+        let width = self.size.0.saturating_mul(self.scale);
+        let height = self.size.1.saturating_mul(self.scale);
+        match (T::try_from(width), T::try_from(height)) {
+            (Ok(w), Ok(h)) => Ok((w, h)),
+            _ => Err(format!("{}x{} is too large, use a smaller --animation-scale", width, height))
+        }
+    }
+
+    // blows every pixel up into a scale by scale square
+    fn scale_up(&self, pixels: &[u8]) -> Vec<u8> {
+        let mut scaled = Vec::with_capacity(pixels.len() * self.scale * self.scale);
+        for row in pixels.chunks(self.size.0) {
+            for _ in 0..self.scale {
+                for &pixel in row {
+                    for _ in 0..self.scale {
+                        scaled.push(pixel);
+                    }
+                }
+            }
+        }
+        scaled
+    }
+
+    pub fn write_gif<W: Write>(&self, writer: W) -> Result<(), String> {
+        let (width, height) = self.scaled_size::<u16>()?;
+        let colors: Vec<u8> = self.palette.colors.iter().flat_map(|color| color.iter().cloned()).collect();
+        let mut encoder = gif::Encoder::new(writer, width, height, &colors)
+            .map_err(|why| why.to_string())?;
+        encoder.set_repeat(gif::Repeat::Infinite).map_err(|why| why.to_string())?;
+
+        // when each frame starts, rounded to hundredths of a second. a frame
+        // starting too soon after the previous one takes its place instead,
+        // so the others keep their timing and the newest display is shown
+        let mut shown: Vec<(&[u8], u32)> = Vec::new();
+        let mut elapsed = 0;
+        for &(ref pixels, duration) in &self.frames {
+            let start = (elapsed * 100 + 30) / 60;
+            match shown.last_mut() {
+                Some(last) if start - last.1 < GIF_MIN_DELAY => last.0 = pixels,
+                _ => shown.push((pixels, start))
+            }
+            elapsed += duration;
+        }
+        let end = ((elapsed * 100 + 30) / 60).max(shown.last().unwrap().1 + GIF_MIN_DELAY);
+
+        for (i, &(pixels, start)) in shown.iter().enumerate() {
+            let next = shown.get(i + 1).map_or(end, |&(_, next)| next);
+            let mut frame = gif::Frame::from_indexed_pixels(width, height, self.scale_up(pixels), None);
+            frame.delay = (next - start) as u16;
+            encoder.write_frame(&frame).map_err(|why| why.to_string())?;
+        }
+        Ok(())
+    }
+
+    pub fn write_apng<W: Write>(&self, writer: W) -> Result<(), String> {
+        let (width, height) = self.scaled_size::<u32>()?;
+        let mut encoder = png::Encoder::new(writer, width, height);
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+        encoder.set_animated(self.frames.len() as u32, 0).map_err(|why| why.to_string())?;
+
+        let mut writer = encoder.write_header().map_err(|why| why.to_string())?;
+        for &(ref pixels, duration) in &self.frames {
+            let rgba: Vec<u8> = self.scale_up(pixels).iter()
+                .flat_map(|&pixel| self.palette.rgba(pixel as usize).to_vec())
+                .collect();
+            writer.set_frame_delay(duration.min(u16::MAX as u32) as u16, 60)
+                .and_then(|_| writer.write_image_data(&rgba))
+                .map_err(|why| why.to_string())?;
+        }
+        writer.finish().map_err(|why| why.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(dedup: bool) -> AnimationRecorder {
+        let mut recorder = AnimationRecorder::new(Palette::default(), 2, dedup);
+        let mut display = Display::new();
+        recorder.capture(&display);
+        recorder.capture(&display);
+        display.draw(0, 0, 1, &[0x80]);
+        recorder.capture(&display);
+        recorder
+    }
+
+    #[test]
+    fn test_dedup() {
+        assert_eq!(record(false).frame_count(), 3);
+
+        let recorder = record(true);
+        assert_eq!(recorder.frame_count(), 2);
+        assert_eq!(recorder.frames[0].1, 2);
+        assert_eq!(recorder.frames[1].1, 1);
+    }
+
+    #[test]
+    fn test_max_frames() {
+        let mut recorder = AnimationRecorder::new(Palette::default(), 1, false);
+        let display = Display::new();
+        for _ in 0..MAX_FRAMES + 10 {
+            recorder.capture(&display);
+        }
+        assert_eq!(recorder.frame_count(), MAX_FRAMES);
+    }
+
+    #[test]
+    fn test_write_gif() {
+        let mut bytes = Vec::new();
+        record(false).write_gif(&mut bytes).unwrap();
+
+        let mut decoder = gif::DecodeOptions::new().read_info(&bytes[..]).unwrap();
+        assert_eq!(decoder.width(), 128);
+        assert_eq!(decoder.height(), 64);
+
+        // the third frame starts too soon after the second and replaces it,
+        // the remaining two still add up to three frames at 60 fps
+        let mut frames = Vec::new();
+        while let Some(frame) = decoder.read_next_frame().unwrap() {
+            frames.push((frame.delay, frame.buffer[0]));
+        }
+        assert_eq!(frames, vec![(2, 0), (3, 1)]);
+    }
+
+    #[test]
+    fn test_write_apng() {
+        let mut bytes = Vec::new();
+        record(true).write_apng(&mut bytes).unwrap();
+
+        let decoder = png::Decoder::new(&bytes[..]);
+        let reader = decoder.read_info().unwrap();
+        let animation = reader.info().animation_control.unwrap();
+        assert_eq!(animation.num_frames, 2);
+        assert_eq!(reader.info().frame_control.unwrap().delay_num, 2);
+        assert_eq!(reader.info().frame_control.unwrap().delay_den, 60);
+    }
+
+    #[test]
+    fn test_too_large() {
+        let mut recorder = AnimationRecorder::new(Palette::default(), 1024, false);
+        recorder.capture(&Display::new());
+        assert!(recorder.write_gif(Vec::new()).is_err());
+    }
+
+    #[test]
+    fn test_save_empty() {
+        let recorder = AnimationRecorder::new(Palette::default(), 1, false);
+        assert!(recorder.save(Path::new("unused.gif")).is_err());
+    }
+}
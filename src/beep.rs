@@ -0,0 +1,45 @@
+/// Samples per second of the beeper, both played and captured
+pub const SAMPLE_RATE: u32 = 44100;
+
+/// The square wave played while the sound timer runs. Doesn't depend on
+/// SDL so captures can generate exactly the samples that would be heard.
+pub struct SquareWave {
+    phase_inc: f32,
+    phase: f32,
+    volume: f32
+}
+
+impl SquareWave {
+    pub fn new(sample_rate: u32) -> Self {
+        SquareWave {
+            phase_inc: 221.0 / sample_rate as f32,
+            phase: 440.0 / sample_rate as f32,
+            volume: 0.05
+        }
+    }
+
+    /// Fills `out` with the next samples of the wave
+    pub fn fill(&mut self, out: &mut [f32]) {
+        for x in out.iter_mut() {
+            *x = self.volume * if self.phase < 0.5 { 1.0 } else { -1.0 };
+            self.phase = (self.phase + self.phase_inc) % 1.0;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fill() {
+        let mut wave = SquareWave::new(SAMPLE_RATE);
+        let mut samples = [0.0; SAMPLE_RATE as usize / 221];
+        wave.fill(&mut samples);
+
+        // one period, half high and half low
+        let high = samples.iter().filter(|&&sample| sample > 0.0).count();
+        assert!((99..=100).contains(&high));
+        assert!(samples.iter().all(|&sample| sample.abs() == 0.05));
+    }
+}
@@ -0,0 +1,253 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Seek, SeekFrom, Write};
+use std::path::Path;
+
+use beep::{SAMPLE_RATE, SquareWave};
+use display::Display;
+use palette::{Palette, Rgb};
+
+const FRAME_RATE: u64 = 60;
+
+/// Writes frames as an uncompressed 4:4:4 YUV4MPEG2 stream at 60 fps
+pub struct Y4mWriter<W: Write> {
+    writer: W,
+    // the palette converted to Y'CbCr
+    colors: [Rgb; 4],
+    scale: usize,
+    size: Option<(usize, usize)>
+}
+
+impl<W: Write> Y4mWriter<W> {
+    pub fn new(writer: W, palette: &Palette, scale: usize) -> Self {
+        let mut colors = [[0; 3]; 4];
+        for (yuv, &rgb) in colors.iter_mut().zip(palette.colors.iter()) {
+            *yuv = to_yuv(rgb);
+        }
+
+        Y4mWriter {
+            writer,
+            colors,
+            scale,
+            size: None
+        }
+    }
+
+    /// Writes the display as the next frame. The stream keeps the size of
+    /// the first frame, later frames of another resolution are stretched.
+    pub fn write_frame(&mut self, display: &Display) -> io::Result<()> {
+        let (width, height) = match self.size {
+            Some(size) => size,
+            None => {
+                let size = (display.width() * self.scale, display.height() * self.scale);
+                writeln!(self.writer, "YUV4MPEG2 W{} H{} F{}:1 Ip A1:1 C444", size.0, size.1, FRAME_RATE)?;
+                self.size = Some(size);
+                size
+            }
+        };
+
+        let pixels = display.pixels();
+        let mut plane = Vec::with_capacity(width * height);
+        self.writer.write_all(b"FRAME\n")?;
+        for channel in 0..3 {
+            plane.clear();
+            for y in 0..height {
+                let row = &pixels[y * display.height() / height];
+                for x in 0..width {
+                    let pixel = row[x * display.width() / width];
+                    plane.push(self.colors[pixel as usize][channel]);
+                }
+            }
+            self.writer.write_all(&plane)?;
+        }
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+// BT.601 with video levels, what players assume for a y4m without a
+// colour range
+fn to_yuv(rgb: Rgb) -> Rgb {
+    let [r, g, b] = [rgb[0] as i32, rgb[1] as i32, rgb[2] as i32];
+    let y = ((66 * r + 129 * g + 25 * b + 128) >> 8) + 16;
+    let u = ((-38 * r - 74 * g + 112 * b + 128) >> 8) + 128;
+    let v = ((112 * r - 94 * g - 18 * b + 128) >> 8) + 128;
+    [y as u8, u as u8, v as u8]
+}
+
+/// Writes mono 16 bit PCM samples as a WAV file. The sizes in the header
+/// are filled in by `finish`.
+pub struct WavWriter<W: Write + Seek> {
+    writer: W,
+    data_size: u32
+}
+
+impl<W: Write + Seek> WavWriter<W> {
+    pub fn new(mut writer: W) -> io::Result<Self> {
+        writer.write_all(b"RIFF")?;
+        writer.write_all(&36u32.to_le_bytes())?;
+        writer.write_all(b"WAVEfmt ")?;
+        writer.write_all(&16u32.to_le_bytes())?;
+        // PCM, one channel
+        writer.write_all(&1u16.to_le_bytes())?;
+        writer.write_all(&1u16.to_le_bytes())?;
+        writer.write_all(&SAMPLE_RATE.to_le_bytes())?;
+        writer.write_all(&(SAMPLE_RATE * 2).to_le_bytes())?;
+        writer.write_all(&2u16.to_le_bytes())?;
+        writer.write_all(&16u16.to_le_bytes())?;
+        writer.write_all(b"data")?;
+        writer.write_all(&0u32.to_le_bytes())?;
+
+        Ok(WavWriter {
+            writer,
+            data_size: 0
+        })
+    }
+
+    pub fn write_samples(&mut self, samples: &[f32]) -> io::Result<()> {
+        for &sample in samples {
+            let sample = (sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16;
+            self.writer.write_all(&sample.to_le_bytes())?;
+        }
+        self.data_size += samples.len() as u32 * 2;
+        Ok(())
+    }
+
+    /// Fills in the sizes and returns the writer
+    pub fn finish(mut self) -> io::Result<W> {
+        self.writer.seek(SeekFrom::Start(4))?;
+        self.writer.write_all(&(36 + self.data_size).to_le_bytes())?;
+        self.writer.seek(SeekFrom::Start(40))?;
+        self.writer.write_all(&self.data_size.to_le_bytes())?;
+        self.writer.seek(SeekFrom::End(0))?;
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+/// Captures the display to a y4m file and the beeper to a wav file, one
+/// video frame and 1/60 second of audio per emulated frame, so both stay in
+/// sync however fast the emulator actually runs
+pub struct Capture {
+    video: Option<Y4mWriter<BufWriter<File>>>,
+    audio: Option<WavWriter<BufWriter<File>>>,
+    wave: SquareWave,
+    samples: Vec<f32>,
+    frame: u64
+}
+
+impl Capture {
+    pub fn create(video_path: Option<&str>, audio_path: Option<&str>, palette: &Palette,
+                  scale: usize) -> Result<Self, String> {
+        let video = match video_path {
+            Some(path) => Some(Y4mWriter::new(BufWriter::new(create_file(path)?), palette, scale)),
+            None => None
+        };
+        let audio = match audio_path {
+            Some(path) => Some(WavWriter::new(BufWriter::new(create_file(path)?))
+                               .map_err(|why| format!("couldn't write {}: {}", path, why))?),
+            None => None
+        };
+
+        Ok(Capture {
+            video,
+            audio,
+            wave: SquareWave::new(SAMPLE_RATE),
+            samples: Vec::new(),
+            frame: 0
+        })
+    }
+
+    /// Adds the frame that just ran, beeping if the sound timer is running
+    pub fn capture_frame(&mut self, display: &Display, beeping: bool) -> Result<(), String> {
+        if let Some(ref mut video) = self.video {
+            video.write_frame(display).map_err(|why| format!("couldn't write video: {}", why))?;
+        }
+
+        // not always a whole number of samples per frame, so count from the
+        // start to avoid drifting
+        let rate = SAMPLE_RATE as u64;
+        let count = ((self.frame + 1) * rate / FRAME_RATE - self.frame * rate / FRAME_RATE) as usize;
+        self.frame += 1;
+        if let Some(ref mut audio) = self.audio {
+            self.samples.clear();
+            self.samples.resize(count, 0.0);
+            if beeping {
+                self.wave.fill(&mut self.samples);
+            }
+            audio.write_samples(&self.samples).map_err(|why| format!("couldn't write audio: {}", why))?;
+        }
+        Ok(())
+    }
+
+    /// Number of frames captured so far
+    pub fn frame_count(&self) -> u64 {
+        self.frame
+    }
+
+    pub fn finish(self) -> Result<(), String> {
+        if let Some(mut video) = self.video {
+            video.flush().map_err(|why| format!("couldn't write video: {}", why))?;
+        }
+        if let Some(audio) = self.audio {
+            audio.finish().map_err(|why| format!("couldn't write audio: {}", why))?;
+        }
+        Ok(())
+    }
+}
+
+fn create_file(path: &str) -> Result<File, String> {
+    File::create(Path::new(path)).map_err(|why| format!("couldn't create {}: {}", path, why))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn test_y4m() {
+        let mut display = Display::new();
+        display.draw(0, 0, 1, &[0x80]);
+
+        let mut bytes = Vec::new();
+        {
+            let mut writer = Y4mWriter::new(&mut bytes, &Palette::default(), 2);
+            writer.write_frame(&display).unwrap();
+            writer.write_frame(&display).unwrap();
+        }
+
+        let header = b"YUV4MPEG2 W128 H64 F60:1 Ip A1:1 C444\n";
+        assert!(bytes.starts_with(header));
+        let frame_size = 6 + 128 * 64 * 3;
+        assert_eq!(bytes.len(), header.len() + 2 * frame_size);
+
+        // the lit pixel covers the top left 2x2 of the luma plane
+        let luma = &bytes[header.len() + 6..];
+        let lit = to_yuv(Palette::default().foreground())[0];
+        let unlit = to_yuv(Palette::default().background())[0];
+        assert_eq!(&luma[..3], &[lit, lit, unlit]);
+        assert_eq!(&luma[128..131], &[lit, lit, unlit]);
+        assert_eq!(luma[256], unlit);
+    }
+
+    #[test]
+    fn test_to_yuv() {
+        assert_eq!(to_yuv([0, 0, 0]), [16, 128, 128]);
+        assert_eq!(to_yuv([255, 255, 255]), [235, 128, 128]);
+    }
+
+    #[test]
+    fn test_wav() {
+        let mut writer = WavWriter::new(Cursor::new(Vec::new())).unwrap();
+        writer.write_samples(&[0.0, 1.0, -1.0]).unwrap();
+        let bytes = writer.finish().unwrap().into_inner();
+
+        assert_eq!(bytes.len(), 44 + 6);
+        assert_eq!(&bytes[4..8], &42u32.to_le_bytes());
+        assert_eq!(&bytes[40..44], &6u32.to_le_bytes());
+        assert_eq!(&bytes[44..], &[0, 0, 0xff, 0x7f, 0x01, 0x80]);
+    }
+}
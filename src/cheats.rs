@@ -0,0 +1,400 @@
+//! A cheat console, started with `--cheats`, for finding and freezing the
+//! addresses games keep things like lives and timers in. Commands are typed
+//! on stdin while the game runs:
+//!
+//! ```text
+//! search               start a new search over all of ram
+//! eq VALUE             keep addresses holding VALUE
+//! changed, unchanged   keep addresses that did or didn't change
+//! inc, dec             keep addresses whose value went up or down
+//! poke ADDRESS VALUE   write VALUE to ADDRESS once
+//! freeze ADDRESS VALUE [NAME]
+//! unfreeze ADDRESS
+//! cheats               list the frozen addresses
+//! ```
+//!
+//! Frozen addresses are saved per ROM and written every frame. Cheats
+//! change the game outside of its input, so they can't be used while a
+//! movie is recorded or played.
+
+use std::fmt::Write as FmtWrite;
+use std::fs;
+use std::io::{self, BufRead};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+
+use config;
+
+// candidates listed after a search step
+const SHOWN_CANDIDATES: usize = 10;
+
+/// How values in ram are compared against the last snapshot
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Comparison {
+    Equal(u8),
+    Changed,
+    Unchanged,
+    Increased,
+    Decreased
+}
+
+impl Comparison {
+    fn matches(&self, old: u8, new: u8) -> bool {
+        match *self {
+            Comparison::Equal(value) => new == value,
+            Comparison::Changed => new != old,
+            Comparison::Unchanged => new == old,
+            Comparison::Increased => new > old,
+            Comparison::Decreased => new < old
+        }
+    }
+}
+
+/// Narrows down the addresses holding a value by comparing snapshots of
+/// ram, e.g. lives going down each time one is lost
+pub struct Search {
+    candidates: Vec<usize>,
+    snapshot: Vec<u8>
+}
+
+impl Search {
+    /// Starts with every address as a candidate
+    pub fn new(ram: &[u8]) -> Self {
+        Search {
+            candidates: (0..ram.len()).collect(),
+            snapshot: ram.to_vec()
+        }
+    }
+
+    /// Keeps the candidates whose values compare as asked, then takes a
+    /// new snapshot to compare the next step against
+    pub fn filter(&mut self, ram: &[u8], comparison: Comparison) {
+        let snapshot = &self.snapshot;
+        self.candidates.retain(|&address| comparison.matches(snapshot[address], ram[address]));
+        self.snapshot = ram.to_vec();
+    }
+
+    pub fn candidates(&self) -> &[usize] {
+        &self.candidates
+    }
+}
+
+/// An address written with the same value every frame
+#[derive(Clone, Debug, PartialEq)]
+pub struct Cheat {
+    pub address: usize,
+    pub value: u8,
+    pub name: String
+}
+
+/// The cheats for a ROM, kept in `cheats/<rom hash>.cheats` in the config
+/// directory as lines like
+///
+/// ```text
+/// 2f2 = 09  # lives
+/// ```
+///
+/// with the address and value in hex
+#[derive(Debug, Default, PartialEq)]
+pub struct Cheats {
+    cheats: Vec<Cheat>
+}
+
+impl Cheats {
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut cheats = Cheats::default();
+        for (number, line) in text.lines().enumerate() {
+            let mut parts = line.splitn(2, '#');
+            let cheat = parts.next().unwrap().trim();
+            if cheat.is_empty() {
+                continue;
+            }
+            let name = parts.next().unwrap_or("").trim().to_string();
+
+            let error = || format!("line {}: expected `address = value`", number + 1);
+            let mut sides = cheat.splitn(2, '=');
+            let address = parse_hex(sides.next().unwrap(), 0xFFF).ok_or_else(error)?;
+            let value = sides.next().and_then(|value| parse_hex(value, 0xFF)).ok_or_else(error)?;
+            cheats.freeze(address, value as u8, &name);
+        }
+        Ok(cheats)
+    }
+
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let text = fs::read_to_string(path)
+            .map_err(|why| format!("couldn't read {}: {}", path.display(), why))?;
+        Cheats::parse(&text).map_err(|why| format!("{}: {}", path.display(), why))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        let mut text = String::new();
+        for cheat in &self.cheats {
+            write!(text, "{:03x} = {:02x}", cheat.address, cheat.value).unwrap();
+            if !cheat.name.is_empty() {
+                write!(text, "  # {}", cheat.name).unwrap();
+            }
+            text.push('\n');
+        }
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)
+                .map_err(|why| format!("couldn't create {}: {}", dir.display(), why))?;
+        }
+        fs::write(path, text).map_err(|why| format!("couldn't write {}: {}", path.display(), why))
+    }
+
+    /// Where the cheats for a ROM are kept, if there is a config directory
+    pub fn path(rom_hash: u64) -> Option<PathBuf> {
+        config::config_dir().map(|dir| dir.join("cheats").join(format!("{:016x}.cheats", rom_hash)))
+    }
+
+    /// Freezes an address, replacing any cheat already on it
+    pub fn freeze(&mut self, address: usize, value: u8, name: &str) {
+        self.unfreeze(address);
+        self.cheats.push(Cheat { address, value, name: name.to_string() });
+    }
+
+    /// Returns whether the address was frozen
+    pub fn unfreeze(&mut self, address: usize) -> bool {
+        let count = self.cheats.len();
+        self.cheats.retain(|cheat| cheat.address != address);
+        self.cheats.len() != count
+    }
+
+    pub fn list(&self) -> &[Cheat] {
+        &self.cheats
+    }
+
+    /// Writes the frozen values, called every frame
+    pub fn apply(&self, ram: &mut [u8]) {
+        for cheat in &self.cheats {
+            ram[cheat.address] = cheat.value;
+        }
+    }
+}
+
+fn parse_hex(text: &str, max: usize) -> Option<usize> {
+    let text = text.trim();
+    let text = text.strip_prefix("0x").unwrap_or(text);
+    usize::from_str_radix(text, 16).ok().filter(|&number| number <= max)
+}
+
+/// Commands typed on stdin while the emulator runs. Lines are read on
+/// another thread and run between frames.
+pub struct Console {
+    lines: Receiver<String>,
+    search: Option<Search>,
+    cheats: Cheats,
+    path: Option<PathBuf>
+}
+
+impl Console {
+    /// Starts reading stdin, with the cheats saved for the ROM frozen
+    pub fn start(rom_hash: u64) -> Self {
+        let (sender, lines) = mpsc::channel();
+        thread::spawn(move || {
+            let stdin = io::stdin();
+            for line in stdin.lock().lines().map_while(Result::ok) {
+                if sender.send(line).is_err() {
+                    break;
+                }
+            }
+        });
+
+        let path = Cheats::path(rom_hash);
+        let cheats = match path {
+            Some(ref path) if path.exists() => Cheats::load(path).unwrap_or_else(|why| {
+                println!("WARNING: {}", why);
+                Cheats::default()
+            }),
+            _ => Cheats::default()
+        };
+        if !cheats.list().is_empty() {
+            println!("{} cheats frozen, type `cheats` to list them", cheats.list().len());
+        }
+        println!("cheat console ready, type `help` for commands");
+
+        Console { lines, search: None, cheats, path }
+    }
+
+    /// Runs the commands typed since the last frame and freezes the cheats
+    pub fn update(&mut self, ram: &mut [u8]) {
+        while let Ok(line) = self.lines.try_recv() {
+            let reply = self.run(&line, ram);
+            if !reply.is_empty() {
+                println!("{}", reply);
+            }
+        }
+        self.cheats.apply(ram);
+    }
+
+    fn run(&mut self, line: &str, ram: &mut [u8]) -> String {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let comparison = match words.as_slice() {
+            [] => return String::new(),
+            ["help"] => return HELP.to_string(),
+            ["search"] => {
+                self.search = Some(Search::new(ram));
+                return format!("searching all {} addresses", ram.len());
+            },
+            ["eq", value] => match parse_hex(value, 0xFF) {
+                Some(value) => Comparison::Equal(value as u8),
+                None => return format!("{} isn't a hex byte", value)
+            },
+            ["changed"] => Comparison::Changed,
+            ["unchanged"] => Comparison::Unchanged,
+            ["inc"] => Comparison::Increased,
+            ["dec"] => Comparison::Decreased,
+            ["poke", address, value] => {
+                return match (parse_hex(address, 0xFFF), parse_hex(value, 0xFF)) {
+                    (Some(address), Some(value)) => {
+                        ram[address] = value as u8;
+                        String::new()
+                    },
+                    _ => "usage: poke ADDRESS VALUE".to_string()
+                };
+            },
+            ["freeze", address, value, name @ ..] => {
+                return match (parse_hex(address, 0xFFF), parse_hex(value, 0xFF)) {
+                    (Some(address), Some(value)) => {
+                        self.cheats.freeze(address, value as u8, &name.join(" "));
+                        self.save()
+                    },
+                    _ => "usage: freeze ADDRESS VALUE [NAME]".to_string()
+                };
+            },
+            ["unfreeze", address] => {
+                return match parse_hex(address, 0xFFF) {
+                    Some(address) if self.cheats.unfreeze(address) => self.save(),
+                    _ => format!("{} isn't frozen", address)
+                };
+            },
+            ["cheats"] => {
+                let lines: Vec<String> = self.cheats.list().iter()
+                    .map(|cheat| format!("{:03x} = {:02x} {}", cheat.address, cheat.value, cheat.name))
+                    .collect();
+                return lines.join("\n");
+            },
+            ["eq", ..] => return "usage: eq VALUE".to_string(),
+            ["poke", ..] => return "usage: poke ADDRESS VALUE".to_string(),
+            ["freeze", ..] => return "usage: freeze ADDRESS VALUE [NAME]".to_string(),
+            ["unfreeze", ..] => return "usage: unfreeze ADDRESS".to_string(),
+            _ => return format!("unknown command {}, type `help` for commands", line.trim())
+        };
+
+        let search = self.search.get_or_insert_with(|| Search::new(ram));
+        search.filter(ram, comparison);
+        let candidates = search.candidates();
+        let mut reply = format!("{} candidates", candidates.len());
+        for &address in candidates.iter().take(SHOWN_CANDIDATES) {
+            write!(reply, "\n{:03x} = {:02x}", address, ram[address]).unwrap();
+        }
+        reply
+    }
+
+    fn save(&self) -> String {
+        match self.path {
+            Some(ref path) => match self.cheats.save(path) {
+                Ok(()) => String::new(),
+                Err(why) => format!("WARNING: {}", why)
+            },
+            None => "WARNING: no config directory to save cheats in".to_string()
+        }
+    }
+}
+
+const HELP: &str = "\
+search               start a new search over all of ram
+eq VALUE             keep addresses holding VALUE
+changed, unchanged   keep addresses that did or didn't change since the last step
+inc, dec             keep addresses whose value went up or down since the last step
+poke ADDRESS VALUE   write VALUE to ADDRESS once
+freeze ADDRESS VALUE [NAME]
+                     write VALUE to ADDRESS every frame, saved for this ROM
+unfreeze ADDRESS     stop freezing ADDRESS
+cheats               list the frozen addresses
+addresses and values are in hex";
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_search() {
+        let mut ram = [0; 8];
+        ram[2] = 3;
+        ram[5] = 3;
+        let mut search = Search::new(&ram);
+        search.filter(&ram, Comparison::Equal(3));
+        assert_eq!(search.candidates(), &[2, 5]);
+
+        ram[2] = 2;
+        search.filter(&ram, Comparison::Decreased);
+        assert_eq!(search.candidates(), &[2]);
+
+        search.filter(&ram, Comparison::Unchanged);
+        assert_eq!(search.candidates(), &[2]);
+        ram[2] = 4;
+        search.filter(&ram, Comparison::Increased);
+        assert_eq!(search.candidates(), &[2]);
+        search.filter(&ram, Comparison::Changed);
+        assert!(search.candidates().is_empty());
+    }
+
+    #[test]
+    fn test_parse_and_save() {
+        let cheats = Cheats::parse("# tetris\n2f2 = 09  # lives\n0x300=ff\n").unwrap();
+        assert_eq!(cheats.list(), &[
+            Cheat { address: 0x2F2, value: 9, name: "lives".to_string() },
+            Cheat { address: 0x300, value: 0xFF, name: String::new() }
+        ]);
+
+        let mut ram = [0; 4096];
+        cheats.apply(&mut ram);
+        assert_eq!(ram[0x2F2], 9);
+        assert_eq!(ram[0x300], 0xFF);
+
+        let path = std::env::temp_dir().join("chip8-test-cheats").join("test.cheats");
+        cheats.save(&path).unwrap();
+        assert_eq!(Cheats::load(&path).unwrap(), cheats);
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+
+        assert!(Cheats::parse("2f2").is_err());
+        assert!(Cheats::parse("1000 = 1").is_err());
+        assert!(Cheats::parse("2f2 = 100").is_err());
+    }
+
+    #[test]
+    fn test_freeze() {
+        let mut cheats = Cheats::default();
+        cheats.freeze(0x2F2, 9, "lives");
+        cheats.freeze(0x2F2, 5, "lives");
+        assert_eq!(cheats.list().len(), 1);
+        assert_eq!(cheats.list()[0].value, 5);
+        assert!(cheats.unfreeze(0x2F2));
+        assert!(!cheats.unfreeze(0x2F2));
+    }
+
+    #[test]
+    fn test_console() {
+        let mut console = Console {
+            lines: mpsc::channel().1,
+            search: None,
+            cheats: Cheats::default(),
+            path: None
+        };
+        let mut ram = [0; 4096];
+        ram[0x2F2] = 3;
+
+        assert_eq!(console.run("eq 3", &mut ram), "1 candidates\n2f2 = 03");
+        assert_eq!(console.run("poke 2f2 2", &mut ram), "");
+        assert_eq!(console.run("dec", &mut ram), "1 candidates\n2f2 = 02");
+        assert!(console.run("freeze 2f2 9 lives", &mut ram).starts_with("WARNING"));
+        assert_eq!(console.run("cheats", &mut ram), "2f2 = 09 lives");
+        assert!(console.run("poke 2f2", &mut ram).starts_with("usage"));
+        assert!(console.run("bogus", &mut ram).starts_with("unknown command"));
+
+        console.update(&mut ram);
+        assert_eq!(ram[0x2F2], 9);
+    }
+}
@@ -0,0 +1,30 @@
+use std::env;
+use std::path::{Path, PathBuf};
+
+/// Directory holding the user's configuration, `$XDG_CONFIG_HOME/chip8`
+/// or `~/.config/chip8`
+pub fn config_dir() -> Option<PathBuf> {
+    if let Some(dir) = env::var_os("XDG_CONFIG_HOME") {
+        return Some(PathBuf::from(dir).join("chip8"));
+    }
+    env::var_os("HOME").map(|home| PathBuf::from(home).join(".config").join("chip8"))
+}
+
+/// File name of a ROM without its extension, used to look up per-ROM
+/// settings
+pub fn rom_name(rom_path: &Path) -> String {
+    rom_path.file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rom_name() {
+        assert_eq!(rom_name(Path::new("assets/pong.ch8")), "pong");
+        assert_eq!(rom_name(Path::new("maze")), "maze");
+    }
+}
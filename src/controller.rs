@@ -0,0 +1,83 @@
+use sdl2::GameControllerSubsystem;
+use sdl2::controller::GameController;
+use sdl2::event::Event;
+
+use keymap::KeyMap;
+use keypad::Keypad;
+
+/// Keeps the connected game controllers open and feeds their buttons into
+/// the keypad. Controllers become players in the order they are plugged
+/// in, and a controller plugged in after another was removed takes over
+/// the free player slot.
+pub struct Controllers {
+    subsystem: GameControllerSubsystem,
+    players: Vec<Option<GameController>>
+}
+
+impl Controllers {
+    pub fn new(subsystem: GameControllerSubsystem) -> Self {
+        Controllers {
+            subsystem,
+            players: Vec::new()
+        }
+    }
+
+    /// Handles controller events. SDL reports controllers that are already
+    /// connected at startup as added as well, so nothing has to be opened
+    /// beforehand.
+    pub fn handle_event(&mut self, event: &Event, keymap: &KeyMap, keypad: &mut Keypad) {
+        match *event {
+            Event::ControllerDeviceAdded {which, ..} => self.add(which),
+            Event::ControllerDeviceRemoved {which, ..} => {
+                if let Some(index) = self.player_index(which) {
+                    self.players[index] = None;
+                }
+            },
+            Event::ControllerButtonDown {which, button, ..} => {
+                let key = self.player(which).and_then(|player| keymap.lookup_button(player, button));
+                if let Some(key) = key {
+                    keypad.press(key);
+                }
+            },
+            Event::ControllerButtonUp {which, button, ..} => {
+                let key = self.player(which).and_then(|player| keymap.lookup_button(player, button));
+                if let Some(key) = key {
+                    keypad.release(key);
+                }
+            },
+            _ => {}
+        }
+    }
+
+    fn add(&mut self, joystick_index: u32) {
+        let controller = match self.subsystem.open(joystick_index) {
+            Ok(controller) => controller,
+            Err(why) => {
+                println!("WARNING: couldn't open controller {}: {}", joystick_index, why);
+                return;
+            }
+        };
+
+        // the same controller can be reported twice at startup
+        if self.player_index(controller.instance_id()).is_some() {
+            return;
+        }
+
+        match self.players.iter().position(|player| player.is_none()) {
+            Some(index) => self.players[index] = Some(controller),
+            None => self.players.push(Some(controller))
+        }
+    }
+
+    fn player_index(&self, instance_id: i32) -> Option<usize> {
+        self.players.iter().position(|player| match *player {
+            Some(ref controller) => controller.instance_id() == instance_id,
+            None => false
+        })
+    }
+
+    // players are numbered from 1 like in key map files
+    fn player(&self, instance_id: i32) -> Option<u8> {
+        self.player_index(instance_id).map(|index| index as u8 + 1)
+    }
+}
@@ -1,14 +1,32 @@
 const RAM_SIZE: usize = 4096;
+// programs are loaded here, below is reserved for the interpreter
+const PROGRAM_START: usize = 0x200;
+/// Largest ROM that fits in memory
+pub const MAX_ROM_SIZE: usize = RAM_SIZE - PROGRAM_START;
+// instructions executed per 60 hz frame, roughly 500 hz
+const CYCLES_PER_FRAME: usize = 8;
 
 mod timers;
+mod state;
+mod rng;
 
 use fonts;
 use std::num::Wrapping;
 use rand;
+use cpu::rng::XorShift;
 use cpu::timers::TimerSubsystem;
 use display::Display;
 use keypad:: Keypad;
 
+/// What the CPU did, kept while tracing is on
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Trace {
+    /// An instruction, logged before it runs
+    Instruction { pc: usize, opcode: u16 },
+    /// A byte an instruction wrote to ram
+    Write { address: usize, value: u8 }
+}
+
 pub struct Chip8 {
     pub ram: [u8; RAM_SIZE],
     stack: [usize; 16],
@@ -17,23 +35,46 @@ pub struct Chip8 {
     pc: usize,
     sp: usize,
     timer_subsystem: TimerSubsystem,
+    seed: u64,
+    rng: XorShift,
+    // what ran since the last take_trace, when tracing is on
+    trace_instructions: bool,
+    trace_writes: bool,
+    trace: Vec<Trace>,
 
     pub display: Display,
     pub keypad: Keypad,
 }
 
+impl Default for Chip8 {
+    fn default() -> Self {
+        Chip8::new()
+    }
+}
+
 impl Chip8 {
     /// Constructs a new virtual chip8 CPU, with standard chip8 fonts loaded
     /// into memory
     pub fn new() -> Self {
+        Chip8::with_seed(rand::random())
+    }
+
+    /// Same as `new`, but with the random number generator used by Cxkk
+    /// seeded, so a run can be reproduced
+    pub fn with_seed(seed: u64) -> Self {
         let mut cpu = Chip8 {
             ram: [0; RAM_SIZE],
             stack: [0; 16],
             v: [0; 16],
             i: 0,
-            pc: 0x200,
+            pc: PROGRAM_START,
             sp: 0,
             timer_subsystem: TimerSubsystem::new(),
+            seed,
+            rng: XorShift::with_seed(seed),
+            trace_instructions: false,
+            trace_writes: false,
+            trace: Vec::new(),
 
             display: Display::new(),
             keypad: Keypad::new()
@@ -48,18 +89,85 @@ impl Chip8 {
     /// Loads a program in the form of a u8 array into the chip8 memory
     pub fn load_rom(&mut self, rom: &[u8]) {
         for (i, &b) in rom.iter().enumerate() {
-            self.ram[i + 0x200] = rom[i];
+            self.ram[i + PROGRAM_START] = rom[i];
         }
     }
 
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
     pub fn cycle(&mut self) {
+        if self.trace_instructions {
+            self.trace.push(Trace::Instruction { pc: self.pc, opcode: self.next_opcode() });
+        }
         let op = self.get_opcode();
         self.run_opcode(op);
-        self.timer_subsystem.cycle();
 
         //println!("{:x}, {}", self.get_opcode(), self.pc);
     }
 
+    /// Runs the instructions for one 60 hz frame and then decrements the
+    /// timers. Frames only depend on the keypad and the seed, never on
+    /// wall time.
+    pub fn run_frame(&mut self) {
+        for _ in 0..CYCLES_PER_FRAME {
+            self.cycle();
+        }
+        self.timer_subsystem.tick();
+    }
+
+    /// The opcode at the program counter, the next one to run
+    pub fn next_opcode(&self) -> u16 {
+        (self.ram[self.pc] as u16) << 8 | (self.ram[self.pc + 1] as u16)
+    }
+
+    pub fn pc(&self) -> usize {
+        self.pc
+    }
+
+    pub fn i(&self) -> usize {
+        self.i
+    }
+
+    pub fn v(&self, x: usize) -> u8 {
+        self.v[x]
+    }
+
+    pub fn set_v(&mut self, x: usize, value: u8) {
+        self.v[x] = value;
+    }
+
+    /// Starts or stops tracing the instructions run and the writes they
+    /// make to ram
+    pub fn set_trace(&mut self, instructions: bool, writes: bool) {
+        self.trace_instructions = instructions;
+        self.trace_writes = writes;
+        self.trace.clear();
+    }
+
+    /// What was traced since the last call, oldest first
+    pub fn take_trace(&mut self) -> Vec<Trace> {
+        std::mem::take(&mut self.trace)
+    }
+
+    fn write(&mut self, address: usize, value: u8) {
+        self.ram[address] = value;
+        if self.trace_writes {
+            self.trace.push(Trace::Write { address, value });
+        }
+    }
+
+    /// Whether the program is stuck jumping to itself, which is how many
+    /// programs stop once they are done
+    pub fn is_halted(&self) -> bool {
+        self.next_opcode() as usize == 0x1000 | self.pc
+    }
+
+    fn random_byte(&mut self) -> u8 {
+        self.rng.next_u32() as u8
+    }
+
     pub fn should_beep(&mut self) -> bool {
         return self.timer_subsystem.sound > 0;
     }
@@ -289,7 +397,7 @@ impl Chip8 {
 
     // Store random byte ANDed by kk in Vx
     fn op_Cxkk(&mut self, x: usize, kk: u8) -> (usize) {
-        let rn: u8 = rand::random();
+        let rn = self.random_byte();
         self.v[x] = rn & kk;
         2
     }
@@ -366,9 +474,10 @@ impl Chip8 {
     
     // Store BCD representation of Vx in ram starting at I
     fn op_Fx33(&mut self, x: usize) -> (usize) {
-        self.ram[self.i] = self.v[x] / 100;
-        self.ram[self.i + 1] = (self.v[x] % 100) / 10;
-        self.ram[self.i + 2] = self.v[x] % 10;
+        let (i, vx) = (self.i, self.v[x]);
+        self.write(i, vx / 100);
+        self.write(i + 1, (vx % 100) / 10);
+        self.write(i + 2, vx % 10);
         2
     }
     
@@ -378,7 +487,8 @@ impl Chip8 {
         let end = if (x > 0xf) {0xf} else {x};
 
         for addr in 0..end + 1 {
-            self.ram[self.i + addr] = self.v[addr];
+            let (i, value) = (self.i, self.v[addr]);
+            self.write(i + addr, value);
         }
         2
     }
@@ -0,0 +1,57 @@
+// the Cxkk random number generator. It makes the same numbers as rand's
+// XorShiftRng seeded with seed_from_u64, so seeds keep giving the same runs,
+// but its state can be saved and restored
+pub struct XorShift {
+    state: [u32; 4]
+}
+
+impl XorShift {
+    pub fn with_seed(mut seed: u64) -> Self {
+        // spreads the seed over the state with PCG32, like rand does
+        let mut state = [0; 4];
+        for word in state.iter_mut() {
+            seed = seed.wrapping_mul(6_364_136_223_846_793_005).wrapping_add(11_634_580_027_462_260_723);
+            let xorshifted = (((seed >> 18) ^ seed) >> 27) as u32;
+            *word = xorshifted.rotate_right((seed >> 59) as u32);
+        }
+        XorShift::from_state(state)
+    }
+
+    pub fn from_state(state: [u32; 4]) -> Self {
+        // xorshift gets stuck on all zeros, so rand swaps them for this
+        if state == [0; 4] {
+            XorShift { state: [0xBAD_5EED; 4] }
+        } else {
+            XorShift { state }
+        }
+    }
+
+    pub fn state(&self) -> [u32; 4] {
+        self.state
+    }
+
+    pub fn next_u32(&mut self) -> u32 {
+        let [x, y, z, w] = self.state;
+        let t = x ^ (x << 11);
+        self.state = [y, z, w, w ^ (w >> 19) ^ t ^ (t >> 8)];
+        self.state[3]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{Rng, SeedableRng};
+    use rand::prng::XorShiftRng;
+
+    #[test]
+    fn test_matches_rand() {
+        for &seed in &[0, 1, 99, u64::MAX] {
+            let mut ours = XorShift::with_seed(seed);
+            let mut theirs = XorShiftRng::seed_from_u64(seed);
+            for _ in 0..100 {
+                assert_eq!(ours.next_u32() as u8, theirs.gen::<u8>());
+            }
+        }
+    }
+}
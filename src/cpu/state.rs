@@ -0,0 +1,128 @@
+use super::{Chip8, RAM_SIZE};
+use super::rng::XorShift;
+use state::{StateReader, StateWriter};
+
+const MAGIC: &[u8; 4] = b"C8ST";
+const VERSION: u8 = 2;
+
+impl Chip8 {
+    /// Snapshots the whole machine. Loading the snapshot into a chip8
+    /// running the same ROM continues exactly where this one was.
+    ///
+    /// The state starts with `C8ST` and a version byte, followed by RAM,
+    /// the stack, registers, timers, the random number generator and the
+    /// display and keypad.
+    pub fn save_state(&self) -> Vec<u8> {
+        let mut state = StateWriter::new();
+        state.write_bytes(MAGIC);
+        state.write_u8(VERSION);
+
+        state.write_bytes(&self.ram);
+        for &address in self.stack.iter() {
+            state.write_u16(address as u16);
+        }
+        state.write_bytes(&self.v);
+        state.write_u16(self.i as u16);
+        state.write_u16(self.pc as u16);
+        state.write_u8(self.sp as u8);
+        state.write_u8(self.timer_subsystem.delay);
+        state.write_u8(self.timer_subsystem.sound);
+        state.write_u64(self.seed);
+        for word in self.rng.state().iter() {
+            state.write_u32(*word);
+        }
+
+        self.display.save_state(&mut state);
+        self.keypad.save_state(&mut state);
+        state.into_bytes()
+    }
+
+    /// Restores a snapshot from `save_state`. The machine is left alone if
+    /// the snapshot is broken.
+    pub fn load_state(&mut self, bytes: &[u8]) -> Result<(), String> {
+        let mut state = StateReader::new(bytes);
+        if state.read_bytes(4)? != MAGIC {
+            return Err("not a save state".to_string());
+        }
+        let version = state.read_u8()?;
+        if version != VERSION {
+            return Err(format!("save state version {} isn't supported", version));
+        }
+
+        let mut cpu = Chip8::with_seed(0);
+        cpu.ram.copy_from_slice(state.read_bytes(RAM_SIZE)?);
+        for address in cpu.stack.iter_mut() {
+            *address = state.read_u16()? as usize;
+        }
+        cpu.v.copy_from_slice(state.read_bytes(16)?);
+        cpu.i = state.read_u16()? as usize;
+        cpu.pc = state.read_u16()? as usize;
+        cpu.sp = state.read_u8()? as usize;
+        cpu.timer_subsystem.delay = state.read_u8()?;
+        cpu.timer_subsystem.sound = state.read_u8()?;
+        cpu.seed = state.read_u64()?;
+        let mut rng = [0; 4];
+        for word in rng.iter_mut() {
+            *word = state.read_u32()?;
+        }
+        cpu.rng = XorShift::from_state(rng);
+
+        cpu.display.load_state(&mut state)?;
+        cpu.keypad.load_state(&mut state)?;
+        state.finish()?;
+
+        if cpu.sp >= cpu.stack.len() || cpu.pc >= RAM_SIZE - 1 || cpu.i > RAM_SIZE {
+            return Err("save state has registers out of range".to_string());
+        }
+
+        cpu.keypad.set_wait_mode(self.keypad.wait_mode());
+        *self = cpu;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // draws a random sprite at a random place, forever
+    const ROM: [u8; 8] = [0xC0, 0x3F, 0xC1, 0x1F, 0xD0, 0x15, 0x12, 0x00];
+
+    #[test]
+    fn test_save_and_load() {
+        let mut a = Chip8::with_seed(99);
+        a.load_rom(&ROM);
+        for _ in 0..10 {
+            a.run_frame();
+        }
+        a.keypad.press(3);
+
+        let state = a.save_state();
+        let mut b = Chip8::with_seed(1);
+        b.load_state(&state).unwrap();
+        assert_eq!(b.save_state(), state);
+
+        for _ in 0..10 {
+            a.run_frame();
+            b.run_frame();
+        }
+        assert_eq!(b.save_state(), a.save_state());
+        assert!(b.keypad.is_pressed(3));
+    }
+
+    #[test]
+    fn test_load_broken_state() {
+        let mut cpu = Chip8::with_seed(5);
+        cpu.load_rom(&ROM);
+        let state = cpu.save_state();
+
+        assert!(cpu.load_state(b"C8MV").is_err());
+        assert!(cpu.load_state(&state[..state.len() - 1]).is_err());
+        let mut longer = state.clone();
+        longer.push(0);
+        assert!(cpu.load_state(&longer).is_err());
+
+        // failed loads leave the machine alone
+        assert_eq!(cpu.save_state(), state);
+    }
+}
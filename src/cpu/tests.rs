@@ -57,6 +57,59 @@ fn test_cycle() {
     assert_eq!(chip8.pc, 0x200);
 }
 
+#[test]
+fn test_run_frame() {
+    let mut chip8 = Chip8::new();
+    chip8.timer_subsystem.delay = 10;
+
+    // jump to self forever
+    chip8.load_rom(&[0x12, 0x00]);
+    chip8.run_frame();
+
+    assert_eq!(chip8.pc, 0x200);
+    assert_eq!(chip8.timer_subsystem.delay, 9);
+}
+
+#[test]
+fn test_is_halted() {
+    let mut chip8 = Chip8::new();
+    chip8.load_rom(&[0x12, 0x02, 0x12, 0x02]);
+    assert!(!chip8.is_halted());
+
+    chip8.cycle();
+    assert!(chip8.is_halted());
+}
+
+#[test]
+fn test_trace_instructions() {
+    let mut chip8 = Chip8::new();
+    chip8.load_rom(&[0x12, 0x00]);
+    chip8.run_frame();
+    assert!(chip8.take_trace().is_empty());
+
+    chip8.set_trace(true, false);
+    chip8.run_frame();
+    assert_eq!(chip8.take_trace(), vec![Trace::Instruction { pc: 0x200, opcode: 0x1200 }; CYCLES_PER_FRAME]);
+}
+
+#[test]
+fn test_trace_writes() {
+    let mut chip8 = Chip8::new();
+    chip8.v[0] = 123;
+    chip8.i = 0x300;
+    chip8.run_opcode(0xF033);
+    assert!(chip8.take_trace().is_empty());
+
+    chip8.set_trace(false, true);
+    chip8.run_opcode(0xF033);
+    assert_eq!(chip8.take_trace(), vec![
+        Trace::Write { address: 0x300, value: 1 },
+        Trace::Write { address: 0x301, value: 2 },
+        Trace::Write { address: 0x302, value: 3 }
+    ]);
+    assert!(chip8.take_trace().is_empty());
+}
+
 #[test]
 fn test_get_opcode() {
     let mut chip8 = make_chip8_and_load_rom();
@@ -218,6 +271,19 @@ fn test_Cnnn() {
     assert_eq!(chip8.v[1], 0);
 }
 
+#[test]
+fn test_Cxkk_seeded() {
+    let mut a = Chip8::with_seed(1234);
+    let mut b = Chip8::with_seed(1234);
+
+    for _ in 0..10 {
+        a.run_opcode(0xC1FF);
+        b.run_opcode(0xC1FF);
+        assert_eq!(a.v[1], b.v[1]);
+    }
+    assert_eq!(a.seed(), 1234);
+}
+
 #[test]
 fn test_Fx1E() {
     let mut chip8 = Chip8::new();
@@ -307,3 +373,20 @@ fn test_Fx15_and_Fx18() {
     assert_eq!(chip8.timer_subsystem.delay, chip8.v[0]);
     assert_eq!(chip8.timer_subsystem.sound, chip8.v[0]);
 }
+
+#[test]
+fn test_Fx0A() {
+    let mut chip8 = Chip8::new();
+    
+    // should keep executing the same instruction until a key is entered
+    chip8.run_opcode(0xF30A);
+    assert_eq!(chip8.pc, 0x200);
+    chip8.keypad.press(0xB);
+    chip8.run_opcode(0xF30A);
+    assert_eq!(chip8.pc, 0x200);
+
+    chip8.keypad.release(0xB);
+    chip8.run_opcode(0xF30A);
+    assert_eq!(chip8.pc, 0x202);
+    assert_eq!(chip8.v[3], 0xB);
+}
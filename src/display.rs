@@ -1,16 +1,19 @@
+use state::{StateReader, StateWriter};
+
 const DISPLAY_HEIGHT: usize = 32;
 const DISPLAY_WIDTH: usize = 64;
 
-use sdl2::video::Window;
-use sdl2::render::Canvas;
-use sdl2::pixels::Color;
-use sdl2::rect::Rect;
-
 pub struct Display {
     pixels: [[bool; 64]; 32],
     changed: bool,
 }
 
+impl Default for Display {
+    fn default() -> Self {
+        Display::new()
+    }
+}
+
 impl Display {
     pub fn new() -> Self {
         Display {
@@ -44,20 +47,19 @@ impl Display {
         pixels_cleared
     }
 
-    pub fn render(&mut self, canvas: &mut Canvas<Window>) {
-        if !self.changed { return }
+    /// Returns whether the pixels changed since the last call
+    pub fn take_changed(&mut self) -> bool {
+        let changed = self.changed;
+        self.changed = false;
+        changed
+    }
 
-        canvas.set_draw_color(Color::RGB(255, 165, 0));
+    pub fn width(&self) -> usize {
+        DISPLAY_WIDTH
+    }
 
-        for y in 0..DISPLAY_HEIGHT {
-            for x in 0..DISPLAY_WIDTH {
-                if self.pixels[y][x] {
-                    let x_coord = (x * 10) as i32;
-                    let y_coord = (y * 10) as i32;
-                    let result = canvas.fill_rect(Rect::new(x_coord, y_coord, 10, 10));
-                }
-            }
-        }
+    pub fn height(&self) -> usize {
+        DISPLAY_HEIGHT
     }
     
     pub fn clear(&mut self) {
@@ -65,6 +67,28 @@ impl Display {
         self.changed = true;
     }
 
+    pub fn pixels(&self) -> &[[bool; DISPLAY_WIDTH]; DISPLAY_HEIGHT] {
+        &self.pixels
+    }
+
+    pub fn save_state(&self, state: &mut StateWriter) {
+        for row in self.pixels.iter() {
+            for &pixel in row.iter() {
+                state.write_bool(pixel);
+            }
+        }
+    }
+
+    pub fn load_state(&mut self, state: &mut StateReader) -> Result<(), String> {
+        for y in 0..DISPLAY_HEIGHT {
+            for x in 0..DISPLAY_WIDTH {
+                self.pixels[y][x] = state.read_bool()?;
+            }
+        }
+        self.changed = true;
+        Ok(())
+    }
+
     pub fn get_pixel(self, x: usize, y: usize) -> (bool) {
         return self.pixels[y][x];
     }
@@ -101,6 +125,17 @@ mod tests {
         assert_eq!(display.pixels[3][0], true);
     }
 
+    #[test]
+    fn test_take_changed() {
+        let mut display = Display::new();
+        assert!(display.take_changed());
+        assert!(!display.take_changed());
+
+        display.draw(0, 0, 1, &[0x80]);
+        assert!(display.take_changed());
+        assert!(!display.take_changed());
+    }
+
     #[test]
     fn test_clear() {
         let mut display = Display::new();
@@ -0,0 +1,205 @@
+//! C ABI for embedding the emulator, built into a shared library with
+//! `cargo rustc --lib --features ffi --crate-type cdylib`. The matching
+//! header is `include/chip8.h`, which the build generates again to check
+//! it is up to date.
+//!
+//! Functions taking a handle do nothing, or return false or 0, when it is
+//! null.
+
+#![allow(clippy::missing_safety_doc)]
+
+use std::ptr;
+use std::slice;
+
+use cpu::{self, Chip8};
+
+/// Bumped whenever a function changes in a way that breaks callers
+pub const CHIP8_API_VERSION: u32 = 1;
+
+/// An emulator created by `chip8_new`
+pub struct Chip8Handle {
+    cpu: Chip8,
+    seed: u64
+}
+
+#[no_mangle]
+pub extern "C" fn chip8_api_version() -> u32 {
+    CHIP8_API_VERSION
+}
+
+/// Creates an emulator with no program loaded. The seed drives the random
+/// numbers of Cxkk, the same seed and input always give the same run.
+#[no_mangle]
+pub extern "C" fn chip8_new(seed: u64) -> *mut Chip8Handle {
+    Box::into_raw(Box::new(Chip8Handle {
+        cpu: Chip8::with_seed(seed),
+        seed
+    }))
+}
+
+/// Destroys an emulator from `chip8_new`
+#[no_mangle]
+pub unsafe extern "C" fn chip8_free(handle: *mut Chip8Handle) {
+    if !handle.is_null() {
+        drop(Box::from_raw(handle));
+    }
+}
+
+/// Resets the emulator and loads a program of `len` bytes. Fails if the
+/// program doesn't fit in memory.
+#[no_mangle]
+pub unsafe extern "C" fn chip8_load_rom(handle: *mut Chip8Handle, rom: *const u8,
+                                        len: usize) -> bool {
+    let handle = match handle.as_mut() {
+        Some(handle) if !rom.is_null() && len <= cpu::MAX_ROM_SIZE => handle,
+        _ => return false
+    };
+    let wait_mode = handle.cpu.keypad.wait_mode();
+    handle.cpu = Chip8::with_seed(handle.seed);
+    handle.cpu.keypad.set_wait_mode(wait_mode);
+    handle.cpu.load_rom(slice::from_raw_parts(rom, len));
+    true
+}
+
+/// Runs one 60 hz frame
+#[no_mangle]
+pub unsafe extern "C" fn chip8_run_frame(handle: *mut Chip8Handle) {
+    if let Some(handle) = handle.as_mut() {
+        handle.cpu.run_frame();
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn chip8_width(handle: *const Chip8Handle) -> usize {
+    handle.as_ref().map_or(0, |handle| handle.cpu.display.width())
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn chip8_height(handle: *const Chip8Handle) -> usize {
+    handle.as_ref().map_or(0, |handle| handle.cpu.display.height())
+}
+
+/// Returns the display as `chip8_width` times `chip8_height` bytes, row by
+/// row, 1 for a lit pixel and 0 otherwise. The pointer stays valid until
+/// the emulator is freed.
+#[no_mangle]
+pub unsafe extern "C" fn chip8_framebuffer(handle: *const Chip8Handle) -> *const u8 {
+    match handle.as_ref() {
+        // bools are single bytes holding 0 or 1
+        Some(handle) => handle.cpu.display.pixels().as_ptr() as *const u8,
+        None => ptr::null()
+    }
+}
+
+/// Presses or releases one of the keys 0 to F
+#[no_mangle]
+pub unsafe extern "C" fn chip8_set_key(handle: *mut Chip8Handle, key: u8, down: bool) -> bool {
+    let handle = match handle.as_mut() {
+        Some(handle) if key <= 0xF => handle,
+        _ => return false
+    };
+    let key = key as usize;
+    if down && !handle.cpu.keypad.is_pressed(key) {
+        handle.cpu.keypad.press(key);
+    } else if !down && handle.cpu.keypad.is_pressed(key) {
+        handle.cpu.keypad.release(key);
+    }
+    true
+}
+
+/// Sets all keys at once, bit n being key n
+#[no_mangle]
+pub unsafe extern "C" fn chip8_set_keys(handle: *mut Chip8Handle, keys: u16) {
+    if let Some(handle) = handle.as_mut() {
+        handle.cpu.keypad.set_state(keys);
+    }
+}
+
+/// Whether the beeper is sounding
+#[no_mangle]
+pub unsafe extern "C" fn chip8_sound_active(handle: *mut Chip8Handle) -> bool {
+    handle.as_mut().is_some_and(|handle| handle.cpu.should_beep())
+}
+
+/// Size of a save state in bytes
+#[no_mangle]
+pub unsafe extern "C" fn chip8_state_size(handle: *const Chip8Handle) -> usize {
+    handle.as_ref().map_or(0, |handle| handle.cpu.save_state().len())
+}
+
+/// Writes a save state into `buffer`, which has to hold at least
+/// `chip8_state_size` bytes
+#[no_mangle]
+pub unsafe extern "C" fn chip8_save_state(handle: *const Chip8Handle, buffer: *mut u8,
+                                          len: usize) -> bool {
+    let state = match handle.as_ref() {
+        Some(handle) if !buffer.is_null() => handle.cpu.save_state(),
+        _ => return false
+    };
+    if state.len() > len {
+        return false;
+    }
+    ptr::copy_nonoverlapping(state.as_ptr(), buffer, state.len());
+    true
+}
+
+/// Restores a save state from `chip8_save_state`. A broken state leaves
+/// the emulator as it was and returns false.
+#[no_mangle]
+pub unsafe extern "C" fn chip8_load_state(handle: *mut Chip8Handle, state: *const u8,
+                                          len: usize) -> bool {
+    match handle.as_mut() {
+        Some(handle) if !state.is_null() => {
+            handle.cpu.load_state(slice::from_raw_parts(state, len)).is_ok()
+        },
+        _ => false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_run_and_restore() {
+        // draws the font sprite for 0 at the top left, then loops
+        let rom = [0xD0, 0x05, 0x12, 0x02];
+        unsafe {
+            let handle = chip8_new(7);
+            assert!(chip8_load_rom(handle, rom.as_ptr(), rom.len()));
+            let mut state = vec![0; chip8_state_size(handle)];
+            assert!(chip8_save_state(handle, state.as_mut_ptr(), state.len()));
+
+            chip8_run_frame(handle);
+            let pixels = slice::from_raw_parts(chip8_framebuffer(handle),
+                                               chip8_width(handle) * chip8_height(handle));
+            assert_eq!(&pixels[..5], &[1, 1, 1, 1, 0]);
+            assert_eq!(pixels[64], 1);
+
+            assert!(chip8_load_state(handle, state.as_ptr(), state.len()));
+            let pixels = slice::from_raw_parts(chip8_framebuffer(handle), 64);
+            assert!(pixels.iter().all(|&pixel| pixel == 0));
+
+            assert!(!chip8_set_key(handle, 0x10, true));
+            assert!(chip8_set_key(handle, 0xA, true));
+            chip8_free(handle);
+        }
+    }
+
+    #[test]
+    fn test_header() {
+        // copy the generated header over include/chip8.h if this fails
+        assert_eq!(include_str!("../include/chip8.h"), include_str!(concat!(env!("OUT_DIR"), "/chip8.h")),
+                   "include/chip8.h is out of date, see {}/chip8.h", env!("OUT_DIR"));
+    }
+
+    #[test]
+    fn test_null_handle() {
+        unsafe {
+            assert!(!chip8_load_rom(ptr::null_mut(), ptr::null(), 0));
+            assert!(chip8_framebuffer(ptr::null()).is_null());
+            assert_eq!(chip8_state_size(ptr::null()), 0);
+            chip8_free(ptr::null_mut());
+        }
+    }
+}
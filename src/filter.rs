@@ -0,0 +1,136 @@
+use display::Display;
+
+// levels below this are too dark to show up in 8 bit colour
+const CUTOFF: f32 = 1.0 / 255.0;
+
+/// How pixels fade out to hide the flicker of sprites being erased and
+/// redrawn every frame
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FilterMode {
+    /// Pixels switch on and off instantly
+    Off,
+    /// Lit pixels glow on after being cleared, keeping `strength` of their
+    /// brightness every frame like a phosphor screen
+    Decay,
+    /// Every frame is mixed with the previous one, `strength` being the
+    /// previous frame's share
+    Blend
+}
+
+impl FilterMode {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "off" => Some(FilterMode::Off),
+            "decay" => Some(FilterMode::Decay),
+            "blend" => Some(FilterMode::Blend),
+            _ => None
+        }
+    }
+}
+
+/// Brightness of every pixel between 0 and 1 after filtering, fed with
+/// the display once per frame
+pub struct Persistence {
+    mode: FilterMode,
+    strength: f32,
+    levels: Vec<f32>,
+    previous: Vec<f32>
+}
+
+impl Persistence {
+    pub fn new(mode: FilterMode, strength: f32) -> Self {
+        Persistence {
+            mode,
+            strength: strength.clamp(0.0, 1.0),
+            levels: Vec::new(),
+            previous: Vec::new()
+        }
+    }
+
+    /// Updates the levels with the display's current pixels
+    pub fn apply(&mut self, display: &Display) {
+        let pixels: Vec<f32> = display.pixels().iter()
+            .flat_map(|row| row.iter().map(|&pixel| pixel as u8 as f32))
+            .collect();
+        if self.levels.len() != pixels.len() {
+            self.levels = pixels.clone();
+            self.previous = pixels;
+            return;
+        }
+
+        for (i, &pixel) in pixels.iter().enumerate() {
+            let level = match self.mode {
+                FilterMode::Off => pixel,
+                FilterMode::Decay => pixel.max(self.levels[i] * self.strength),
+                FilterMode::Blend => pixel * (1.0 - self.strength) + self.previous[i] * self.strength
+            };
+            self.levels[i] = if level < CUTOFF { 0.0 } else { level };
+        }
+        self.previous = pixels;
+    }
+
+    /// Whether applying the same display again would leave the levels as
+    /// they are, so nothing needs to be redrawn
+    pub fn is_settled(&self) -> bool {
+        self.levels.iter().zip(self.previous.iter()).all(|(&level, &pixel)| level == pixel)
+    }
+
+    pub fn levels(&self) -> &[f32] {
+        &self.levels
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lit_display() -> Display {
+        let mut display = Display::new();
+        display.draw(0, 0, 1, &[0x80]);
+        display
+    }
+
+    #[test]
+    fn test_off() {
+        let mut persistence = Persistence::new(FilterMode::Off, 0.5);
+        persistence.apply(&lit_display());
+        persistence.apply(&Display::new());
+
+        assert_eq!(persistence.levels()[0], 0.0);
+        assert!(persistence.is_settled());
+    }
+
+    #[test]
+    fn test_decay() {
+        let mut persistence = Persistence::new(FilterMode::Decay, 0.5);
+        persistence.apply(&lit_display());
+        assert_eq!(persistence.levels()[0], 1.0);
+
+        persistence.apply(&Display::new());
+        assert_eq!(persistence.levels()[0], 0.5);
+        assert!(!persistence.is_settled());
+        persistence.apply(&Display::new());
+        assert_eq!(persistence.levels()[0], 0.25);
+
+        // fades out completely eventually
+        for _ in 0..10 {
+            persistence.apply(&Display::new());
+        }
+        assert_eq!(persistence.levels()[0], 0.0);
+        assert!(persistence.is_settled());
+    }
+
+    #[test]
+    fn test_blend() {
+        let mut persistence = Persistence::new(FilterMode::Blend, 0.5);
+        persistence.apply(&Display::new());
+        persistence.apply(&lit_display());
+        assert_eq!(persistence.levels()[0], 0.5);
+
+        persistence.apply(&Display::new());
+        assert_eq!(persistence.levels()[0], 0.5);
+        persistence.apply(&Display::new());
+        assert_eq!(persistence.levels()[0], 0.0);
+        assert!(persistence.is_settled());
+    }
+}
@@ -0,0 +1,252 @@
+//! A reinforcement learning environment in the style of OpenAI Gym. An
+//! agent picks one of a small set of actions, each a combination of held
+//! keys, and gets back the display, a reward read from the program's RAM
+//! and whether the episode is over.
+
+use cpu::{self, Chip8};
+
+/// How the reward is read from RAM after each frame. Scores are the
+/// binary coded decimal digits programs write with Fx33 to draw them.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Reward {
+    /// Always 0, for programs without a known score
+    None,
+    /// Score in the three digits at an address, the reward being how much
+    /// it went up
+    Score { address: usize },
+    /// Scores of two players sharing one Fx33 number, the player's in the
+    /// hundreds and tens and the opponent's in the ones. The reward is the
+    /// player's gain minus the opponent's.
+    ScoreDifference { address: usize }
+}
+
+impl Reward {
+    fn score(&self, ram: &[u8]) -> i32 {
+        let digit = |address: usize| ram[address] as i32;
+        match *self {
+            Reward::None => 0,
+            Reward::Score { address } => {
+                digit(address) * 100 + digit(address + 1) * 10 + digit(address + 2)
+            },
+            Reward::ScoreDifference { address } => {
+                digit(address) * 10 + digit(address + 1) - digit(address + 2)
+            }
+        }
+    }
+}
+
+/// Settings for an environment
+#[derive(Clone, Debug, PartialEq)]
+pub struct EnvConfig {
+    /// Frames each action is held for
+    pub frame_skip: usize,
+    /// Keys held for each action, as keypad bitmasks
+    pub actions: Vec<u16>,
+    pub reward: Reward,
+    /// Ends episodes after this many frames, for programs that never stop
+    pub max_frames: Option<usize>,
+    /// Seed of the first episode, each later episode uses the next one
+    pub seed: u64
+}
+
+impl EnvConfig {
+    /// Settings for one of the bundled ROMs, by file name without the
+    /// extension. Other programs get every single key as an action and no
+    /// reward.
+    pub fn for_rom(name: &str) -> Self {
+        let (actions, reward): (&[usize], Reward) = match name {
+            // up and down
+            "pong" => (&[0x1, 0x4], Reward::ScoreDifference { address: 0x2F2 }),
+            // rotate, left, right and drop
+            "tetris" => (&[0x4, 0x5, 0x6, 0x7], Reward::Score { address: 0x804 }),
+            // left, fire and right
+            "spaceinvaders" => (&[0x4, 0x5, 0x6], Reward::None),
+            _ => (&[0x0, 0x1, 0x2, 0x3, 0x4, 0x5, 0x6, 0x7,
+                    0x8, 0x9, 0xA, 0xB, 0xC, 0xD, 0xE, 0xF], Reward::None)
+        };
+
+        // doing nothing is always the first action
+        let mut masks = vec![0];
+        masks.extend(actions.iter().map(|&key| 1 << key));
+        EnvConfig {
+            frame_skip: 4,
+            actions: masks,
+            reward,
+            max_frames: Some(60 * 60 * 5),
+            seed: 0
+        }
+    }
+}
+
+/// What a step returns
+#[derive(Clone, Debug, PartialEq)]
+pub struct Step {
+    pub observation: Vec<u8>,
+    pub reward: f32,
+    pub done: bool
+}
+
+pub struct Env {
+    config: EnvConfig,
+    rom: Vec<u8>,
+    cpu: Chip8,
+    episode: u64,
+    frame: usize,
+    score: i32
+}
+
+impl Env {
+    pub fn new(rom: &[u8], config: EnvConfig) -> Result<Self, String> {
+        if rom.len() > cpu::MAX_ROM_SIZE {
+            return Err(format!("ROM is {} bytes, at most {} fit in memory",
+                               rom.len(), cpu::MAX_ROM_SIZE));
+        }
+        if config.actions.is_empty() || config.frame_skip == 0 {
+            return Err("an environment needs actions and a frame skip of at least 1".to_string());
+        }
+        let cpu = Chip8::with_seed(config.seed);
+        if let Reward::Score { address } | Reward::ScoreDifference { address } = config.reward {
+            if address.saturating_add(2) >= cpu.ram.len() {
+                return Err(format!("the reward's digits at {:#x} don't fit in memory", address));
+            }
+        }
+
+        let mut env = Env {
+            cpu,
+            config,
+            rom: rom.to_vec(),
+            episode: 0,
+            frame: 0,
+            score: 0
+        };
+        env.reset();
+        env.episode = 0;
+        Ok(env)
+    }
+
+    /// Starts a new episode and returns the first observation
+    pub fn reset(&mut self) -> Vec<u8> {
+        self.cpu = Chip8::with_seed(self.config.seed.wrapping_add(self.episode));
+        self.cpu.load_rom(&self.rom);
+        self.episode += 1;
+        self.frame = 0;
+        self.score = self.config.reward.score(&self.cpu.ram);
+        self.observation()
+    }
+
+    /// Holds the keys of an action for `frame_skip` frames
+    pub fn step(&mut self, action: usize) -> Result<Step, String> {
+        let keys = *self.config.actions.get(action)
+            .ok_or_else(|| format!("there are only {} actions", self.config.actions.len()))?;
+        self.cpu.keypad.set_state(keys);
+
+        let mut done = false;
+        for _ in 0..self.config.frame_skip {
+            self.cpu.run_frame();
+            self.frame += 1;
+            done = self.cpu.is_halted() || self.config.max_frames.is_some_and(|max| self.frame >= max);
+            if done {
+                break;
+            }
+        }
+
+        let score = self.config.reward.score(&self.cpu.ram);
+        let reward = (score - self.score) as f32;
+        self.score = score;
+        Ok(Step {
+            observation: self.observation(),
+            reward,
+            done
+        })
+    }
+
+    /// The display, one byte per pixel row by row, 1 for lit pixels
+    pub fn observation(&self) -> Vec<u8> {
+        self.cpu.display.pixels().iter()
+            .flat_map(|row| row.iter().map(|&pixel| pixel as u8))
+            .collect()
+    }
+
+    pub fn action_count(&self) -> usize {
+        self.config.actions.len()
+    }
+
+    /// Width and height of observations
+    pub fn observation_size(&self) -> (usize, usize) {
+        (self.cpu.display.width(), self.cpu.display.height())
+    }
+
+    pub fn cpu(&self) -> &Chip8 {
+        &self.cpu
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(actions: Vec<u16>, reward: Reward) -> EnvConfig {
+        EnvConfig {
+            frame_skip: 2,
+            actions,
+            reward,
+            max_frames: None,
+            seed: 0
+        }
+    }
+
+    #[test]
+    fn test_rewards() {
+        let mut ram = [0; 4096];
+        ram[0x300..0x303].copy_from_slice(&[1, 2, 3]);
+        assert_eq!(Reward::None.score(&ram), 0);
+        assert_eq!(Reward::Score { address: 0x300 }.score(&ram), 123);
+        assert_eq!(Reward::ScoreDifference { address: 0x300 }.score(&ram), 12 - 3);
+    }
+
+    #[test]
+    fn test_step() {
+        // V0 = 7, I = 0x300, BCD of V0 to I, then halt
+        let rom = [0x60, 0x07, 0xA3, 0x00, 0xF0, 0x33, 0x12, 0x06];
+        let mut env = Env::new(&rom, config(vec![0, 1 << 5], Reward::Score { address: 0x300 })).unwrap();
+        assert_eq!(env.observation_size(), (64, 32));
+        assert_eq!(env.reset().len(), 64 * 32);
+
+        let step = env.step(1).unwrap();
+        assert_eq!(step.reward, 7.0);
+        assert!(step.done);
+        assert!(env.cpu().keypad.is_pressed(5));
+        assert!(env.step(2).is_err());
+
+        // a new episode starts from scratch
+        env.reset();
+        assert_eq!(env.step(0).unwrap().reward, 7.0);
+    }
+
+    #[test]
+    fn test_max_frames() {
+        // loops without halting
+        let rom = [0x70, 0x01, 0x12, 0x00];
+        let mut config = config(vec![0], Reward::None);
+        config.max_frames = Some(3);
+        let mut env = Env::new(&rom, config).unwrap();
+
+        assert!(!env.step(0).unwrap().done);
+        assert!(env.step(0).unwrap().done);
+    }
+
+    #[test]
+    fn test_reward_address() {
+        let rom = [0x12, 0x00];
+        assert!(Env::new(&rom, config(vec![0], Reward::Score { address: 0xFFD })).is_ok());
+        assert!(Env::new(&rom, config(vec![0], Reward::Score { address: 0xFFE })).is_err());
+        assert!(Env::new(&rom, config(vec![0], Reward::ScoreDifference { address: usize::MAX })).is_err());
+    }
+
+    #[test]
+    fn test_for_rom() {
+        let pong = EnvConfig::for_rom("pong");
+        assert_eq!(pong.actions, vec![0, 1 << 1, 1 << 4]);
+        assert_eq!(EnvConfig::for_rom("maze").actions.len(), 17);
+    }
+}
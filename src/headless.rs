@@ -0,0 +1,68 @@
+use std::path::Path;
+
+use cpu::Chip8;
+use movie::{self, Movie};
+use options::Options;
+use screenshot;
+use animation::AnimationRecorder;
+use capture::Capture;
+use script::Script;
+
+/// Replays a movie without opening a window, then prints how many frames
+/// ran and a hash of the final display so runs can be compared, e.g. in CI.
+/// The final display is saved if a screenshot was asked for, and every
+/// frame if an animation or capture was. A script runs alongside, and a
+/// failing script fails the run so test scripts can be used in CI.
+pub fn run(options: &Options, cpu: &mut Chip8, movie: &Movie) -> Result<(), String> {
+    let mut capture = if options.captures() {
+        Some(Capture::create(options.video_path.as_deref(),
+                             options.audio_path.as_deref(),
+                             &options.palette, options.video_scale)?)
+    } else {
+        None
+    };
+    let mut animation = options.animation_path.as_ref().map(|_| {
+        AnimationRecorder::new(options.palette, options.animation_scale, options.dedup)
+    });
+    let mut script = options.script_path.as_ref().map(|path| Script::load(path)).transpose()?;
+    let mut frames = 0;
+    for frame in 0..movie.frame_count() {
+        cpu.keypad.set_state(movie.frame(frame).unwrap());
+        if let Some(ref mut script) = script {
+            script.before_frame(cpu)?;
+        }
+        cpu.run_frame();
+        if let Some(ref mut script) = script {
+            script.after_frame(cpu)?;
+        }
+        frames += 1;
+        if let Some(ref mut animation) = animation {
+            animation.capture(&cpu.display);
+        }
+        if let Some(ref mut capture) = capture {
+            let beeping = cpu.should_beep();
+            capture.capture_frame(&cpu.display, beeping)?;
+        }
+        if script.as_ref().is_some_and(|script| script.quit()) {
+            break;
+        }
+    }
+
+    let pixels: Vec<u8> = cpu.display.pixels().iter()
+        .flat_map(|row| row.iter().map(|&pixel| pixel as u8))
+        .collect();
+    println!("frames: {}", frames);
+    println!("display: {:016x}", movie::hash(&pixels));
+
+    if let Some(ref path) = options.screenshot_path {
+        screenshot::save_png(Path::new(path), &cpu.display, &options.palette,
+                             options.screenshot_scale)?;
+    }
+    if let (Some(path), Some(animation)) = (&options.animation_path, animation) {
+        animation.save(Path::new(path))?;
+    }
+    if let Some(capture) = capture {
+        capture.finish()?;
+    }
+    Ok(())
+}
@@ -0,0 +1,63 @@
+use keypad::Keypad;
+
+/// Live input from the keyboard, controllers and overlay. Events press and
+/// release keys on `keys` as they come in, and the chip8 gets them once a
+/// frame through `Keypad::set_state`, the same way a movie's are, so a
+/// recording replays exactly.
+pub struct Input {
+    pub keys: Keypad,
+    // keys that went down since the last frame
+    tapped: u16,
+    // what the chip8 was last given
+    sent: u16
+}
+
+impl Input {
+    pub fn new() -> Self {
+        Input {
+            keys: Keypad::new(),
+            tapped: 0,
+            sent: 0
+        }
+    }
+
+    /// Lets `handle` press and release keys for one event
+    pub fn handle<F: FnOnce(&mut Keypad)>(&mut self, handle: F) {
+        let held = self.keys.state();
+        handle(&mut self.keys);
+        self.tapped |= self.keys.state() & !held;
+    }
+
+    /// The keys for the next frame, or None if they didn't change so keys
+    /// a script pressed stay down. A key pressed and released between two
+    /// frames is still held for one, so the program sees both.
+    pub fn next_frame(&mut self) -> Option<u16> {
+        let keys = self.keys.state() | self.tapped;
+        self.tapped = 0;
+        if keys == self.sent {
+            return None;
+        }
+        self.sent = keys;
+        Some(keys)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tap() {
+        let mut input = Input::new();
+        assert_eq!(input.next_frame(), None);
+
+        input.handle(|keys| keys.press(5));
+        input.handle(|keys| keys.release(5));
+        assert_eq!(input.next_frame(), Some(1 << 5));
+        assert_eq!(input.next_frame(), Some(0));
+
+        input.handle(|keys| keys.press(3));
+        assert_eq!(input.next_frame(), Some(1 << 3));
+        assert_eq!(input.next_frame(), None);
+    }
+}
@@ -0,0 +1,367 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+use sdl2::controller::Button;
+use sdl2::event::Event;
+use sdl2::keyboard::{Keycode, Scancode};
+
+use keypad::Keypad;
+
+/// A host key, either by the character it produces or by its position, or
+/// a button on the game controller of a player (counting from 1)
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Binding {
+    Key(Keycode),
+    Scan(Scancode),
+    Pad(u8, Button)
+}
+
+use self::Binding::{Key, Scan, Pad};
+
+// each preset lists the host keys for the chip8 keys laid out as
+// 1 2 3 C / 4 5 6 D / 7 8 9 E / A 0 B F
+const LAYOUT: [usize; 16] = [
+    0x1, 0x2, 0x3, 0xC,
+    0x4, 0x5, 0x6, 0xD,
+    0x7, 0x8, 0x9, 0xE,
+    0xA, 0x0, 0xB, 0xF
+];
+
+const QWERTY: [Binding; 16] = [
+    Key(Keycode::Num1), Key(Keycode::Num2), Key(Keycode::Num3), Key(Keycode::Num4),
+    Key(Keycode::Q), Key(Keycode::W), Key(Keycode::E), Key(Keycode::R),
+    Key(Keycode::A), Key(Keycode::S), Key(Keycode::D), Key(Keycode::F),
+    Key(Keycode::Z), Key(Keycode::X), Key(Keycode::C), Key(Keycode::V)
+];
+
+// the number row needs shift on AZERTY, so it is bound by position
+const AZERTY: [Binding; 16] = [
+    Scan(Scancode::Num1), Scan(Scancode::Num2), Scan(Scancode::Num3), Scan(Scancode::Num4),
+    Key(Keycode::A), Key(Keycode::Z), Key(Keycode::E), Key(Keycode::R),
+    Key(Keycode::Q), Key(Keycode::S), Key(Keycode::D), Key(Keycode::F),
+    Key(Keycode::W), Key(Keycode::X), Key(Keycode::C), Key(Keycode::V)
+];
+
+const DVORAK: [Binding; 16] = [
+    Key(Keycode::Num1), Key(Keycode::Num2), Key(Keycode::Num3), Key(Keycode::Num4),
+    Key(Keycode::Quote), Key(Keycode::Comma), Key(Keycode::Period), Key(Keycode::P),
+    Key(Keycode::A), Key(Keycode::O), Key(Keycode::E), Key(Keycode::U),
+    Key(Keycode::Semicolon), Key(Keycode::Q), Key(Keycode::J), Key(Keycode::K)
+];
+
+// digits go to the matching keypad digits, the operators down the right
+// hand side take C to F
+const NUMPAD: [Binding; 16] = [
+    Key(Keycode::Kp1), Key(Keycode::Kp2), Key(Keycode::Kp3), Key(Keycode::KpDivide),
+    Key(Keycode::Kp4), Key(Keycode::Kp5), Key(Keycode::Kp6), Key(Keycode::KpMultiply),
+    Key(Keycode::Kp7), Key(Keycode::Kp8), Key(Keycode::Kp9), Key(Keycode::KpMinus),
+    Key(Keycode::KpPeriod), Key(Keycode::Kp0), Key(Keycode::KpEnter), Key(Keycode::KpPlus)
+];
+
+const PRESETS: [(&str, &[Binding; 16]); 4] = [
+    ("qwerty", &QWERTY),
+    ("azerty", &AZERTY),
+    ("dvorak", &DVORAK),
+    ("numpad", &NUMPAD)
+];
+
+// controller layout used when there is no profile for a ROM. the d-pad
+// acts as the usual 2/4/6/8 arrows and 5 is fire
+const DEFAULT_PADS: &str = "
+    pad1 dpup = 2
+    pad1 dpleft = 4
+    pad1 dpright = 6
+    pad1 dpdown = 8
+    pad1 a = 5
+    pad1 b = 0
+    pad1 x = a
+    pad1 y = b
+    pad1 leftshoulder = c
+    pad1 rightshoulder = d
+    pad1 back = e
+    pad1 start = f
+";
+
+// controller profiles for the bundled ROMs
+const PAD_PROFILES: [(&str, &str); 3] = [
+    ("pong", "
+        pad1 dpup = 1
+        pad1 dpdown = 4
+        pad2 dpup = c
+        pad2 dpdown = d
+    "),
+    ("tetris", "
+        pad1 a = 4
+        pad1 dpleft = 5
+        pad1 dpright = 6
+        pad1 dpdown = 7
+    "),
+    ("spaceinvaders", "
+        pad1 dpleft = 4
+        pad1 dpright = 6
+        pad1 a = 5
+    ")
+];
+
+/// Translates host keys into chip8 keys. Keys without a binding are ignored.
+pub struct KeyMap {
+    bindings: HashMap<Binding, usize>
+}
+
+impl KeyMap {
+    pub fn new() -> Self {
+        KeyMap { bindings: HashMap::new() }
+    }
+
+    /// Returns the controller bindings for a ROM, falling back to a generic
+    /// layout for ROMs without their own profile
+    pub fn controller_profile(rom_name: &str) -> Self {
+        let profile = PAD_PROFILES.iter()
+            .find(|&&(name, _)| name == rom_name)
+            .map(|&(_, profile)| profile)
+            .unwrap_or(DEFAULT_PADS);
+
+        KeyMap::parse(profile).unwrap()
+    }
+
+    /// Returns one of the built in layouts: qwerty, azerty, dvorak or numpad
+    pub fn preset(name: &str) -> Option<Self> {
+        let &(_, preset) = PRESETS.iter().find(|&&(preset, _)| preset == name)?;
+        let bindings = preset.iter().cloned().zip(LAYOUT.iter().cloned()).collect();
+        Some(KeyMap { bindings })
+    }
+
+    /// Names of the built in layouts
+    pub fn preset_names() -> impl Iterator<Item = &'static str> {
+        PRESETS.iter().map(|&(name, _)| name)
+    }
+
+    /// Parses a key map where every line is one of
+    ///
+    /// ```text
+    /// preset azerty      # start from a built in layout
+    /// key Q = 4          # bind by the character a key produces
+    /// scancode Q = 4     # bind by the key's physical position
+    /// key V = none       # remove a binding
+    /// pad2 dpup = c      # bind a button on the second player's controller
+    /// ```
+    ///
+    /// Buttons use SDL's names: a, b, x, y, back, guide, start, leftstick,
+    /// rightstick, leftshoulder, rightshoulder, dpup, dpdown, dpleft, dpright
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut keymap = KeyMap::new();
+        keymap.apply(text)?;
+        Ok(keymap)
+    }
+
+    /// Adds the bindings of a built in layout on top of this map
+    pub fn apply_preset(&mut self, name: &str) -> Result<(), String> {
+        let preset = KeyMap::preset(name).ok_or_else(|| format!("unknown preset {}", name))?;
+        self.bindings.extend(preset.bindings);
+        Ok(())
+    }
+
+    /// Applies the lines of a key map file on top of this map
+    pub fn apply_file(&mut self, path: &Path) -> Result<(), String> {
+        let text = fs::read_to_string(path)
+            .map_err(|why| format!("couldn't read {}: {}", path.display(), why))?;
+        self.apply(&text).map_err(|why| format!("{}: {}", path.display(), why))
+    }
+
+    fn apply(&mut self, text: &str) -> Result<(), String> {
+        for (number, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap().trim();
+            if line.is_empty() {
+                continue;
+            }
+            self.apply_line(line).map_err(|why| format!("line {}: {}", number + 1, why))?;
+        }
+        Ok(())
+    }
+
+    fn apply_line(&mut self, line: &str) -> Result<(), String> {
+        if let Some(name) = line.strip_prefix("preset ") {
+            return self.apply_preset(name.trim());
+        }
+
+        let mut sides = line.splitn(2, '=');
+        let host = sides.next().unwrap().trim();
+        let target = sides.next().ok_or("expected `=`")?.trim();
+
+        let binding = KeyMap::parse_binding(host)?;
+        if target == "none" {
+            self.bindings.remove(&binding);
+            return Ok(());
+        }
+        match usize::from_str_radix(target, 16) {
+            Ok(key) if key < 16 => { self.bindings.insert(binding, key); },
+            _ => return Err(format!("{} is not a chip8 key", target))
+        }
+        Ok(())
+    }
+
+    fn parse_binding(host: &str) -> Result<Binding, String> {
+        let mut words = host.splitn(2, ' ');
+        let kind = words.next().unwrap();
+        let name = words.next().unwrap_or("").trim();
+
+        let binding = match kind {
+            "key" => KeyMap::keycode_from_name(name).map(Key),
+            "scancode" => Scancode::from_name(name).map(Scan),
+            _ => match kind.strip_prefix("pad") {
+                Some(player) => {
+                    let player = player.parse::<u8>()
+                        .map_err(|_| format!("bad controller {}", kind))?;
+                    KeyMap::button_from_name(name).map(|button| Pad(player, button))
+                },
+                None => return Err(format!("expected `key`, `scancode` or `padN`, got {}", kind))
+            }
+        };
+        binding.ok_or_else(|| format!("unknown key {}", name))
+    }
+
+    fn button_from_name(name: &str) -> Option<Button> {
+        let button = match name {
+            "a" => Button::A,
+            "b" => Button::B,
+            "x" => Button::X,
+            "y" => Button::Y,
+            "back" => Button::Back,
+            "guide" => Button::Guide,
+            "start" => Button::Start,
+            "leftstick" => Button::LeftStick,
+            "rightstick" => Button::RightStick,
+            "leftshoulder" => Button::LeftShoulder,
+            "rightshoulder" => Button::RightShoulder,
+            "dpup" => Button::DPadUp,
+            "dpdown" => Button::DPadDown,
+            "dpleft" => Button::DPadLeft,
+            "dpright" => Button::DPadRight,
+            _ => return None
+        };
+        Some(button)
+    }
+
+    // SDL keycodes of printable keys are the character they produce
+    fn keycode_from_name(name: &str) -> Option<Keycode> {
+        let mut chars = name.chars();
+        match (chars.next(), chars.next()) {
+            (Some(c), None) if c.is_ascii() => {
+                Keycode::from_i32(c.to_ascii_lowercase() as i32)
+            },
+            _ => Keycode::from_name(name)
+        }
+    }
+
+    /// Returns the chip8 key bound to a host key, bindings by character
+    /// win over bindings by position
+    pub fn lookup(&self, keycode: Option<Keycode>, scancode: Option<Scancode>) -> Option<usize> {
+        keycode.and_then(|keycode| self.bindings.get(&Key(keycode)))
+            .or_else(|| scancode.and_then(|scancode| self.bindings.get(&Scan(scancode))))
+            .cloned()
+    }
+
+    /// Returns the chip8 key bound to a button of a player's controller
+    pub fn lookup_button(&self, player: u8, button: Button) -> Option<usize> {
+        self.bindings.get(&Pad(player, button)).cloned()
+    }
+
+    /// Presses or releases the chip8 key bound to a keyboard event
+    pub fn handle_event(&self, event: &Event, keypad: &mut Keypad) {
+        match *event {
+            Event::KeyDown {keycode, scancode, repeat: false, ..} => {
+                if let Some(key) = self.lookup(keycode, scancode) {
+                    keypad.press(key);
+                }
+            },
+            Event::KeyUp {keycode, scancode, ..} => {
+                if let Some(key) = self.lookup(keycode, scancode) {
+                    keypad.release(key);
+                }
+            },
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_presets() {
+        let qwerty = KeyMap::preset("qwerty").unwrap();
+        assert_eq!(qwerty.lookup(Some(Keycode::Num4), None), Some(0xC));
+        assert_eq!(qwerty.lookup(Some(Keycode::X), None), Some(0));
+        assert_eq!(qwerty.lookup(Some(Keycode::V), None), Some(0xF));
+
+        let azerty = KeyMap::preset("azerty").unwrap();
+        assert_eq!(azerty.lookup(Some(Keycode::Ampersand), Some(Scancode::Num1)), Some(1));
+        assert_eq!(azerty.lookup(Some(Keycode::A), Some(Scancode::Q)), Some(4));
+
+        let numpad = KeyMap::preset("numpad").unwrap();
+        assert_eq!(numpad.lookup(Some(Keycode::Kp0), None), Some(0));
+        assert_eq!(numpad.lookup(Some(Keycode::KpPlus), None), Some(0xF));
+
+        assert!(KeyMap::preset("colemak").is_none());
+    }
+
+    #[test]
+    fn test_unmapped_keys_ignored() {
+        let qwerty = KeyMap::preset("qwerty").unwrap();
+        assert_eq!(qwerty.lookup(Some(Keycode::Space), Some(Scancode::Space)), None);
+        assert_eq!(qwerty.lookup(None, None), None);
+    }
+
+    #[test]
+    fn test_parse() {
+        let keymap = KeyMap::parse("
+            # arrows for pong
+            preset qwerty
+            key w = 1
+            key S = 4
+            key v = none
+        ").unwrap();
+
+        assert_eq!(keymap.lookup(Some(Keycode::W), None), Some(1));
+        assert_eq!(keymap.lookup(Some(Keycode::S), None), Some(4));
+        assert_eq!(keymap.lookup(Some(Keycode::V), None), None);
+        assert_eq!(keymap.lookup(Some(Keycode::C), None), Some(0xB));
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert!(KeyMap::parse("key q 4").is_err());
+        assert!(KeyMap::parse("key q = 10").is_err());
+        assert!(KeyMap::parse("button q = 1").is_err());
+        assert!(KeyMap::parse("preset colemak").is_err());
+        assert!(KeyMap::parse("padone a = 1").is_err());
+        assert!(KeyMap::parse("pad1 z = 1").is_err());
+    }
+
+    #[test]
+    fn test_parse_buttons() {
+        let keymap = KeyMap::parse("
+            pad1 a = 5
+            pad2 dpleft = c
+        ").unwrap();
+
+        assert_eq!(keymap.lookup_button(1, Button::A), Some(5));
+        assert_eq!(keymap.lookup_button(2, Button::DPadLeft), Some(0xC));
+        assert_eq!(keymap.lookup_button(2, Button::A), None);
+    }
+
+    #[test]
+    fn test_controller_profile() {
+        let pong = KeyMap::controller_profile("pong");
+        assert_eq!(pong.lookup_button(1, Button::DPadUp), Some(1));
+        assert_eq!(pong.lookup_button(1, Button::DPadDown), Some(4));
+        assert_eq!(pong.lookup_button(2, Button::DPadUp), Some(0xC));
+        assert_eq!(pong.lookup_button(2, Button::DPadDown), Some(0xD));
+
+        let default = KeyMap::controller_profile("maze");
+        assert_eq!(default.lookup_button(1, Button::DPadUp), Some(2));
+        assert_eq!(default.lookup_button(1, Button::A), Some(5));
+    }
+}
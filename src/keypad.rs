@@ -1,65 +1,151 @@
-use sdl2::event::Event;
-use sdl2::keyboard::Keycode;
+use state::{StateReader, StateWriter};
+
+/// Decides when `Fx0A` considers a key to have been entered
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum WaitMode {
+    /// Key has to be pressed and released again, like on the COSMAC VIP
+    Release,
+    /// Key only has to be pressed
+    Press
+}
 
 pub struct Keypad {
     keys: [bool; 16],
+    wait_mode: WaitMode,
     waiting_for_keypress: bool,
-    last_key_pressed: usize
+    // keys that went down after the current wait started. only releasing
+    // one of these can finish a wait, so a key that was already held
+    // doesn't count
+    pressed_while_waiting: [bool; 16],
+    last_key_pressed: usize,
+    // keys the program checked since the last call to take_polled
+    polled: [bool; 16]
+}
+
+impl Default for Keypad {
+    fn default() -> Self {
+        Keypad::new()
+    }
 }
 
 impl Keypad {
     pub fn new() -> Self {
         Keypad {
             keys: [false; 16],
+            wait_mode: WaitMode::Release,
             waiting_for_keypress: false,
-            last_key_pressed: 0x10
+            pressed_while_waiting: [false; 16],
+            last_key_pressed: 0x10,
+            polled: [false; 16]
         }
     }
     
     pub fn get_key(&mut self, key: usize) -> bool {
-        return self.keys[key];
+        self.polled[key] = true;
+        self.keys[key]
     }
 
-    pub fn handle_event(&mut self, event: &Event) {
-        let event_keycode: Keycode;
-        
-        match event {
-            Event::KeyDown {keycode, ..} => { 
-                self.keys[Keypad::map_key(keycode.unwrap())] = true; 
-            },
-            Event::KeyUp {keycode, ..} => { 
-                self.keys[Keypad::map_key(keycode.unwrap())] = false; 
-            },
-            _ => {}
+    /// Whether a key is held down, without counting as a poll
+    pub fn is_pressed(&self, key: usize) -> bool {
+        self.keys[key]
+    }
+
+    /// Returns the held keys as a bitmask, bit n being key n
+    pub fn state(&self) -> u16 {
+        self.keys.iter().enumerate()
+            .filter(|&(_, &down)| down)
+            .fold(0, |state, (key, _)| state | 1 << key)
+    }
+
+    /// Presses and releases keys to match a bitmask from `state`
+    pub fn set_state(&mut self, state: u16) {
+        for key in 0..16 {
+            if state & 1 << key != 0 {
+                self.press(key);
+            } else if self.keys[key] {
+                self.release(key);
+            }
         }
     }
 
-    fn map_key(keycode: Keycode) -> usize {
-        println!("yay");
-        match keycode {
-            Keycode::Num1 => 1,
-            Keycode::Num2 => 2,
-            Keycode::Num3 => 3,
-            Keycode::Num4 => 0xC,
-            Keycode::Q => 4,
-            Keycode::W => 5,
-            Keycode::E => 6,
-            Keycode::R => 0xD,
-            Keycode::A => 7,
-            Keycode::S => 8,
-            Keycode::D => 9,
-            Keycode::F => 0xE,
-            Keycode::Z => 0xA,
-            Keycode::X => 0,
-            Keycode::C => 0xB,
-            Keycode::V => 0xF,
-            _ => 0
+    /// Returns which keys the program checked since the last call. While
+    /// waiting for a keypress every key counts as checked.
+    pub fn take_polled(&mut self) -> [bool; 16] {
+        let polled = self.polled;
+        self.polled = [false; 16];
+        polled
+    }
+
+    pub fn set_wait_mode(&mut self, mode: WaitMode) {
+        self.wait_mode = mode;
+    }
+
+    pub fn wait_mode(&self) -> WaitMode {
+        self.wait_mode
+    }
+
+    /// Marks a key as held down
+    pub fn press(&mut self, key: usize) {
+        if !self.keys[key] && self.waiting_for_keypress {
+            match self.wait_mode {
+                WaitMode::Press => self.finish_wait(key),
+                WaitMode::Release => self.pressed_while_waiting[key] = true
+            }
+        }
+        self.keys[key] = true;
+    }
+
+    /// Marks a key as no longer held down
+    pub fn release(&mut self, key: usize) {
+        if self.keys[key] && self.waiting_for_keypress &&
+           self.wait_mode == WaitMode::Release &&
+           self.pressed_while_waiting[key] {
+            self.finish_wait(key);
+        }
+        self.keys[key] = false;
+    }
+
+    /// Saves the held keys and any wait in progress. The wait mode is a
+    /// setting and isn't saved.
+    pub fn save_state(&self, state: &mut StateWriter) {
+        state.write_u16(self.state());
+        state.write_bool(self.waiting_for_keypress);
+        for &pressed in self.pressed_while_waiting.iter() {
+            state.write_bool(pressed);
+        }
+        state.write_u8(self.last_key_pressed as u8);
+    }
+
+    pub fn load_state(&mut self, state: &mut StateReader) -> Result<(), String> {
+        let keys = state.read_u16()?;
+        for key in 0..16 {
+            self.keys[key] = keys & 1 << key != 0;
+        }
+        self.waiting_for_keypress = state.read_bool()?;
+        for pressed in self.pressed_while_waiting.iter_mut() {
+            *pressed = state.read_bool()?;
+        }
+        self.last_key_pressed = match state.read_u8()? {
+            key @ 0..=0x10 => key as usize,
+            key => return Err(format!("save state has key {:x} entered", key))
+        };
+        Ok(())
+    }
+
+    // only the first key entered during a wait is kept
+    fn finish_wait(&mut self, key: usize) {
+        if self.last_key_pressed == 0x10 {
+            self.last_key_pressed = key;
         }
     }
 
-    /// Return 0x10 if a key has not been pressed since first wait,
-    /// otherwise it will return the number of the last key pressed
+    /// Return 0x10 if a key has not been entered since first wait,
+    /// otherwise it will return the number of the key entered.
+    ///
+    /// Depending on the wait mode a key counts as entered once it is
+    /// pressed, or once it is pressed and then released.
     pub fn wait_for_keypress(&mut self) -> u8 {
+        self.polled = [true; 16];
         if self.waiting_for_keypress {
             if self.last_key_pressed != 0x10 {
                 self.waiting_for_keypress = false;
@@ -67,6 +153,7 @@ impl Keypad {
         }
         else {
             self.last_key_pressed = 0x10;
+            self.pressed_while_waiting = [false; 16];
             self.waiting_for_keypress = true;
         }
 
@@ -87,6 +174,34 @@ mod tests {
         assert!(!keypad.get_key(1));
     }
 
+    #[test]
+    pub fn test_state() {
+        let mut keypad = Keypad::new();
+        keypad.press(0);
+        keypad.press(0xC);
+        assert_eq!(keypad.state(), 0x1001);
+
+        keypad.set_state(0x0012);
+        assert!(!keypad.is_pressed(0) && !keypad.is_pressed(0xC));
+        assert!(keypad.is_pressed(1) && keypad.is_pressed(4));
+        assert_eq!(keypad.state(), 0x0012);
+    }
+
+    #[test]
+    pub fn test_take_polled() {
+        let mut keypad = Keypad::new();
+        keypad.get_key(4);
+        keypad.get_key(0xF);
+
+        let polled = keypad.take_polled();
+        assert!(polled[4] && polled[0xF]);
+        assert!(!polled[0]);
+        assert_eq!(keypad.take_polled(), [false; 16]);
+
+        keypad.wait_for_keypress();
+        assert_eq!(keypad.take_polled(), [true; 16]);
+    }
+
     #[test]
     pub fn test_wait_for_keypress() {
         let mut keypad = Keypad::new();
@@ -97,4 +212,45 @@ mod tests {
         assert_eq!(keypad.wait_for_keypress(), 1);
         assert!(!keypad.waiting_for_keypress);
     }
+
+    #[test]
+    pub fn test_wait_for_release() {
+        let mut keypad = Keypad::new();
+        assert_eq!(keypad.wait_for_keypress(), 0x10);
+
+        // pressing alone isn't enough
+        keypad.press(5);
+        assert!(keypad.get_key(5));
+        assert_eq!(keypad.wait_for_keypress(), 0x10);
+
+        keypad.release(5);
+        assert!(!keypad.get_key(5));
+        assert_eq!(keypad.wait_for_keypress(), 5);
+        assert!(!keypad.waiting_for_keypress);
+    }
+
+    #[test]
+    pub fn test_wait_ignores_held_key() {
+        let mut keypad = Keypad::new();
+        keypad.press(3);
+        assert_eq!(keypad.wait_for_keypress(), 0x10);
+
+        // key was already down when the wait started
+        keypad.release(3);
+        assert_eq!(keypad.wait_for_keypress(), 0x10);
+
+        keypad.press(0xA);
+        keypad.release(0xA);
+        assert_eq!(keypad.wait_for_keypress(), 0xA);
+    }
+
+    #[test]
+    pub fn test_wait_for_press() {
+        let mut keypad = Keypad::new();
+        keypad.set_wait_mode(WaitMode::Press);
+        assert_eq!(keypad.wait_for_keypress(), 0x10);
+
+        keypad.press(7);
+        assert_eq!(keypad.wait_for_keypress(), 7);
+    }
 }
@@ -0,0 +1,101 @@
+use sdl2::rect::Rect;
+
+/// How the display is scaled up to fit the window
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Scaling {
+    /// Whole multiples only, so every chip8 pixel has the same size
+    Integer,
+    /// As large as fits, keeping the aspect ratio
+    Fractional
+}
+
+impl Scaling {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "integer" => Some(Scaling::Integer),
+            "fractional" => Some(Scaling::Fractional),
+            _ => None
+        }
+    }
+
+    pub fn toggled(self) -> Self {
+        match self {
+            Scaling::Integer => Scaling::Fractional,
+            Scaling::Fractional => Scaling::Integer
+        }
+    }
+}
+
+// space between the display and the keypad panel
+const GAP: u32 = 10;
+
+/// Where the display and the keypad panel go in the window
+#[derive(Debug, PartialEq)]
+pub struct Layout {
+    pub display: Rect,
+    pub panel: (i32, i32)
+}
+
+impl Layout {
+    /// Fits the display into the window next to a square panel of
+    /// `panel_size`, keeping the display's aspect ratio. Whatever space is
+    /// left over is split evenly around them as black bars.
+    pub fn new(window: (u32, u32), display: (u32, u32), panel_size: u32, scaling: Scaling) -> Self {
+        let available_width = window.0.saturating_sub(panel_size + GAP).max(1);
+        let scale = (available_width as f32 / display.0 as f32)
+            .min(window.1 as f32 / display.1 as f32);
+        let scale = match scaling {
+            // never smaller than the native size
+            Scaling::Integer => scale.floor().max(1.0),
+            Scaling::Fractional => scale
+        };
+
+        let width = (display.0 as f32 * scale) as u32;
+        let height = (display.1 as f32 * scale) as u32;
+        let left = (window.0 as i32 - (width + GAP + panel_size) as i32) / 2;
+        let top = (window.1 as i32 - height as i32) / 2;
+
+        Layout {
+            display: Rect::new(left, top, width, height),
+            panel: (left + (width + GAP) as i32, top)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_integer_scaling() {
+        let layout = Layout::new((800, 600), (64, 32), 148, Scaling::Integer);
+
+        assert_eq!(layout.display, Rect::new(1, 140, 640, 320));
+        assert_eq!(layout.panel, (651, 140));
+    }
+
+    #[test]
+    fn test_fractional_scaling() {
+        let layout = Layout::new((1000, 300), (64, 32), 100, Scaling::Fractional);
+
+        // height is the limit, so bars go left and right
+        assert_eq!(layout.display, Rect::new(145, 0, 600, 300));
+        assert_eq!(layout.panel, (755, 0));
+    }
+
+    #[test]
+    fn test_hires_display() {
+        let layout = Layout::new((800, 600), (128, 64), 148, Scaling::Integer);
+
+        assert_eq!(layout.display.width(), 640);
+        assert_eq!(layout.display.height(), 320);
+    }
+
+    #[test]
+    fn test_tiny_window() {
+        let layout = Layout::new((50, 20), (64, 32), 148, Scaling::Integer);
+
+        assert_eq!(layout.display.width(), 64);
+        assert_eq!(layout.display.height(), 32);
+    }
+}
@@ -0,0 +1,36 @@
+//! The emulator core, free of SDL and wall-clock time so it can be built
+//! for `wasm32-unknown-unknown` as well as embedded in the frontends
+
+extern crate rand;
+#[cfg(target_arch = "wasm32")]
+extern crate wasm_bindgen;
+#[cfg(feature = "libretro")]
+extern crate libretro_sys;
+#[cfg(feature = "python")]
+extern crate pyo3;
+// pyo3's macros refer to ::core, which the 2015 edition doesn't provide
+// without declaring it
+#[cfg(feature = "python")]
+extern crate core;
+
+pub mod cpu;
+pub mod fonts;
+pub mod display;
+pub mod keypad;
+pub mod movie;
+pub mod palette;
+pub mod beep;
+pub mod state;
+pub mod gym;
+
+#[cfg(target_arch = "wasm32")]
+pub mod wasm;
+
+#[cfg(feature = "libretro")]
+pub mod libretro;
+
+#[cfg(feature = "ffi")]
+pub mod ffi;
+
+#[cfg(feature = "python")]
+mod python;
@@ -0,0 +1,405 @@
+//! A libretro core, built with `cargo rustc --lib --features libretro
+//! --crate-type cdylib` and loaded by RetroArch or any other libretro
+//! frontend
+
+// the pointers passed to the entry points are only as safe as the frontend
+// makes them, following libretro.h
+#![allow(clippy::missing_safety_doc)]
+
+use std::ffi::CStr;
+use std::os::raw::{c_char, c_uint, c_void};
+use std::ptr;
+use std::slice;
+use std::sync::Mutex;
+
+use libretro_sys::*;
+
+use beep::{SAMPLE_RATE, SquareWave};
+use cpu::{self, Chip8};
+use movie;
+use palette::Palette;
+
+const FRAME_RATE: f64 = 60.0;
+
+// the RetroPad has exactly one button for each chip8 key. the d-pad acts
+// as the usual 2/4/6/8 arrows with 5 as fire, like the default controller
+// layout of the SDL frontend
+const BUTTONS: [(c_uint, usize, &[u8]); 16] = [
+    (DEVICE_ID_JOYPAD_UP, 0x2, b"Key 2 (up)\0"),
+    (DEVICE_ID_JOYPAD_LEFT, 0x4, b"Key 4 (left)\0"),
+    (DEVICE_ID_JOYPAD_RIGHT, 0x6, b"Key 6 (right)\0"),
+    (DEVICE_ID_JOYPAD_DOWN, 0x8, b"Key 8 (down)\0"),
+    (DEVICE_ID_JOYPAD_A, 0x5, b"Key 5 (fire)\0"),
+    (DEVICE_ID_JOYPAD_B, 0x0, b"Key 0\0"),
+    (DEVICE_ID_JOYPAD_X, 0xA, b"Key A\0"),
+    (DEVICE_ID_JOYPAD_Y, 0xB, b"Key B\0"),
+    (DEVICE_ID_JOYPAD_L, 0xC, b"Key C\0"),
+    (DEVICE_ID_JOYPAD_R, 0xD, b"Key D\0"),
+    (DEVICE_ID_JOYPAD_SELECT, 0xE, b"Key E\0"),
+    (DEVICE_ID_JOYPAD_START, 0xF, b"Key F\0"),
+    (DEVICE_ID_JOYPAD_L2, 0x1, b"Key 1\0"),
+    (DEVICE_ID_JOYPAD_R2, 0x3, b"Key 3\0"),
+    (DEVICE_ID_JOYPAD_L3, 0x7, b"Key 7\0"),
+    (DEVICE_ID_JOYPAD_R3, 0x9, b"Key 9\0")
+];
+// both ports press the same keys, which covers two player ROMs like pong
+const PORTS: c_uint = 2;
+
+struct Callbacks {
+    environment: Option<EnvironmentFn>,
+    video_refresh: Option<VideoRefreshFn>,
+    audio_sample_batch: Option<AudioSampleBatchFn>,
+    input_poll: Option<InputPollFn>,
+    input_state: Option<InputStateFn>
+}
+
+static CALLBACKS: Mutex<Callbacks> = Mutex::new(Callbacks {
+    environment: None,
+    video_refresh: None,
+    audio_sample_batch: None,
+    input_poll: None,
+    input_state: None
+});
+
+struct Core {
+    // boxed so the RAM handed out by retro_get_memory_data stays put
+    cpu: Box<Chip8>,
+    rom: Vec<u8>,
+    palette: Palette,
+    wave: SquareWave,
+    frame: u64,
+    video: Vec<u32>,
+    audio: Vec<i16>
+}
+
+impl Core {
+    // the seed comes from the ROM so every run is the same, which netplay
+    // and input recording rely on
+    fn new(rom: Vec<u8>) -> Self {
+        let mut cpu = Box::new(Chip8::with_seed(movie::hash(&rom)));
+        cpu.load_rom(&rom);
+        Core {
+            cpu,
+            rom,
+            palette: Palette::default(),
+            wave: SquareWave::new(SAMPLE_RATE),
+            frame: 0,
+            video: Vec::new(),
+            audio: Vec::new()
+        }
+    }
+
+    fn reset(&mut self) {
+        *self.cpu = Chip8::with_seed(movie::hash(&self.rom));
+        self.cpu.load_rom(&self.rom);
+    }
+
+    fn run(&mut self, callbacks: &Callbacks) {
+        if let Some(input_poll) = callbacks.input_poll {
+            unsafe { input_poll() };
+        }
+        if let Some(input_state) = callbacks.input_state {
+            let mut keys = 0;
+            for port in 0..PORTS {
+                for &(id, key, _) in BUTTONS.iter() {
+                    if unsafe { input_state(port, DEVICE_JOYPAD, 0, id) } != 0 {
+                        keys |= 1 << key;
+                    }
+                }
+            }
+            self.cpu.keypad.set_state(keys);
+        }
+
+        self.cpu.run_frame();
+
+        // XRGB8888
+        self.video.clear();
+        for row in self.cpu.display.pixels().iter() {
+            for &pixel in row.iter() {
+                let [r, g, b] = self.palette.colors[pixel as usize];
+                self.video.push((r as u32) << 16 | (g as u32) << 8 | b as u32);
+            }
+        }
+        if let Some(video_refresh) = callbacks.video_refresh {
+            let width = self.cpu.display.width();
+            unsafe {
+                video_refresh(self.video.as_ptr() as *const c_void, width as c_uint,
+                              self.cpu.display.height() as c_uint, width * 4);
+            }
+        }
+
+        // count from the first frame so the samples never drift from the
+        // frames, then play them on both channels
+        let rate = SAMPLE_RATE as u64;
+        let count = ((self.frame + 1) * rate / 60 - self.frame * rate / 60) as usize;
+        self.frame += 1;
+        let mut samples = vec![0.0; count];
+        if self.cpu.should_beep() {
+            self.wave.fill(&mut samples);
+        }
+        self.audio.clear();
+        for sample in samples {
+            let sample = (sample * i16::MAX as f32) as i16;
+            self.audio.push(sample);
+            self.audio.push(sample);
+        }
+        if let Some(audio_sample_batch) = callbacks.audio_sample_batch {
+            unsafe { audio_sample_batch(self.audio.as_ptr(), count) };
+        }
+    }
+}
+
+static CORE: Mutex<Option<Core>> = Mutex::new(None);
+
+fn environment(command: c_uint, data: *mut c_void) -> bool {
+    match CALLBACKS.lock().unwrap().environment {
+        Some(environment) => unsafe { environment(command, data) },
+        None => false
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn retro_api_version() -> c_uint {
+    API_VERSION
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn retro_get_system_info(info: *mut SystemInfo) {
+    *info = SystemInfo {
+        library_name: b"Chip8\0".as_ptr() as *const c_char,
+        library_version: concat!(env!("CARGO_PKG_VERSION"), "\0").as_ptr() as *const c_char,
+        valid_extensions: b"ch8|c8|sc8|xo8\0".as_ptr() as *const c_char,
+        need_fullpath: false,
+        block_extract: false
+    };
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn retro_get_system_av_info(info: *mut SystemAvInfo) {
+    let display = ::display::Display::new();
+    let (width, height) = (display.width() as c_uint, display.height() as c_uint);
+    *info = SystemAvInfo {
+        geometry: GameGeometry {
+            base_width: width,
+            base_height: height,
+            max_width: width,
+            max_height: height,
+            aspect_ratio: width as f32 / height as f32
+        },
+        timing: SystemTiming {
+            fps: FRAME_RATE,
+            sample_rate: SAMPLE_RATE as f64
+        }
+    };
+}
+
+#[no_mangle]
+pub extern "C" fn retro_set_environment(callback: EnvironmentFn) {
+    CALLBACKS.lock().unwrap().environment = Some(callback);
+}
+
+#[no_mangle]
+pub extern "C" fn retro_set_video_refresh(callback: VideoRefreshFn) {
+    CALLBACKS.lock().unwrap().video_refresh = Some(callback);
+}
+
+#[no_mangle]
+pub extern "C" fn retro_set_audio_sample(_callback: AudioSampleFn) {
+    // samples go out a frame at a time through the batch callback
+}
+
+#[no_mangle]
+pub extern "C" fn retro_set_audio_sample_batch(callback: AudioSampleBatchFn) {
+    CALLBACKS.lock().unwrap().audio_sample_batch = Some(callback);
+}
+
+#[no_mangle]
+pub extern "C" fn retro_set_input_poll(callback: InputPollFn) {
+    CALLBACKS.lock().unwrap().input_poll = Some(callback);
+}
+
+#[no_mangle]
+pub extern "C" fn retro_set_input_state(callback: InputStateFn) {
+    CALLBACKS.lock().unwrap().input_state = Some(callback);
+}
+
+#[no_mangle]
+pub extern "C" fn retro_set_controller_port_device(_port: c_uint, _device: c_uint) {}
+
+#[no_mangle]
+pub extern "C" fn retro_init() {}
+
+#[no_mangle]
+pub extern "C" fn retro_deinit() {
+    *CORE.lock().unwrap() = None;
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn retro_load_game(game: *const GameInfo) -> bool {
+    if game.is_null() || (*game).data.is_null() {
+        return false;
+    }
+    let rom = slice::from_raw_parts((*game).data as *const u8, (*game).size);
+    if rom.len() > cpu::MAX_ROM_SIZE {
+        return false;
+    }
+
+    let mut format = PixelFormat::ARGB8888;
+    if !environment(ENVIRONMENT_SET_PIXEL_FORMAT, &mut format as *mut PixelFormat as *mut c_void) {
+        return false;
+    }
+
+    let mut descriptors = Vec::new();
+    for port in 0..PORTS {
+        for &(id, _, description) in BUTTONS.iter() {
+            descriptors.push(InputDescriptor {
+                port,
+                device: DEVICE_JOYPAD,
+                index: 0,
+                id,
+                description: CStr::from_bytes_with_nul_unchecked(description).as_ptr()
+            });
+        }
+    }
+    descriptors.push(InputDescriptor {
+        port: 0,
+        device: 0,
+        index: 0,
+        id: 0,
+        description: ptr::null()
+    });
+    environment(ENVIRONMENT_SET_INPUT_DESCRIPTORS, descriptors.as_mut_ptr() as *mut c_void);
+
+    *CORE.lock().unwrap() = Some(Core::new(rom.to_vec()));
+    true
+}
+
+#[no_mangle]
+pub extern "C" fn retro_load_game_special(_game_type: c_uint, _info: *const GameInfo,
+                                          _num_info: usize) -> bool {
+    false
+}
+
+#[no_mangle]
+pub extern "C" fn retro_unload_game() {
+    *CORE.lock().unwrap() = None;
+}
+
+#[no_mangle]
+pub extern "C" fn retro_get_region() -> c_uint {
+    Region::NTSC.to_uint()
+}
+
+#[no_mangle]
+pub extern "C" fn retro_reset() {
+    if let Some(ref mut core) = *CORE.lock().unwrap() {
+        core.reset();
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn retro_run() {
+    // frontends don't call back into the core from these callbacks, so
+    // holding both locks is fine
+    let callbacks = CALLBACKS.lock().unwrap();
+    if let Some(ref mut core) = *CORE.lock().unwrap() {
+        core.run(&callbacks);
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn retro_serialize_size() -> usize {
+    match *CORE.lock().unwrap() {
+        Some(ref core) => core.cpu.save_state().len(),
+        None => 0
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn retro_serialize(data: *mut c_void, size: usize) -> bool {
+    match *CORE.lock().unwrap() {
+        Some(ref core) => {
+            let state = core.cpu.save_state();
+            if state.len() > size {
+                return false;
+            }
+            ptr::copy_nonoverlapping(state.as_ptr(), data as *mut u8, state.len());
+            true
+        },
+        None => false
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn retro_unserialize(data: *const c_void, size: usize) -> bool {
+    match *CORE.lock().unwrap() {
+        Some(ref mut core) => {
+            let state = slice::from_raw_parts(data as *const u8, size);
+            core.cpu.load_state(state).is_ok()
+        },
+        None => false
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn retro_cheat_reset() {}
+
+#[no_mangle]
+pub extern "C" fn retro_cheat_set(_index: c_uint, _enabled: bool, _code: *const c_char) {}
+
+/// Exposes the 4 KB of RAM, which lets frontends search and poke memory
+#[no_mangle]
+pub extern "C" fn retro_get_memory_data(id: c_uint) -> *mut c_void {
+    match *CORE.lock().unwrap() {
+        Some(ref mut core) if id == MEMORY_SYSTEM_RAM => core.cpu.ram.as_mut_ptr() as *mut c_void,
+        _ => ptr::null_mut()
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn retro_get_memory_size(id: c_uint) -> usize {
+    match *CORE.lock().unwrap() {
+        Some(ref core) if id == MEMORY_SYSTEM_RAM => core.cpu.ram.len(),
+        _ => 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_buttons() {
+        // every key is on exactly one button, and every button is different
+        let mut keys = [0; 16];
+        let mut ids = Vec::new();
+        for &(id, key, description) in BUTTONS.iter() {
+            keys[key] += 1;
+            ids.push(id);
+            assert!(description.ends_with(b"\0"));
+        }
+        assert!(keys.iter().all(|&count| count == 1));
+        ids.sort();
+        ids.dedup();
+        assert_eq!(ids.len(), 16);
+    }
+
+    #[test]
+    fn test_reset() {
+        let mut core = Core::new(vec![0xC0, 0xFF, 0x12, 0x02]);
+        let fresh = core.cpu.save_state();
+        let callbacks = Callbacks {
+            environment: None,
+            video_refresh: None,
+            audio_sample_batch: None,
+            input_poll: None,
+            input_state: None
+        };
+        core.run(&callbacks);
+        assert_eq!(core.audio.len(), 735 * 2);
+        assert_ne!(core.cpu.save_state(), fresh);
+
+        core.reset();
+        assert_eq!(core.cpu.save_state(), fresh);
+    }
+}
@@ -1,63 +1,358 @@
-mod cpu;
-mod fonts;
 mod rom;
-mod display;
-mod keypad;
+mod patch;
 mod audio;
+mod options;
+mod config;
+mod keymap;
+mod input;
+mod controller;
+mod overlay;
+mod headless;
+mod renderer;
+mod layout;
+mod filter;
+mod postfx;
+mod screenshot;
+mod animation;
+mod capture;
+mod tui;
+mod termgfx;
+mod script;
+mod cheats;
 
-extern crate rand;
+extern crate chip8;
 extern crate sdl2;
+extern crate png;
+extern crate gif;
+extern crate crossterm;
+extern crate rhai;
+extern crate zip;
+
+use chip8::{cpu, fonts, display, keypad, movie, palette, beep};
 
 use std::thread::sleep;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 use std::env;
+use std::process;
+use std::path::Path;
 
 use sdl2::event::Event;
 use sdl2::pixels::Color;
+use sdl2::keyboard::Keycode;
+use sdl2::video::{FullscreenType, Window};
+
+use options::Options;
+use keymap::KeyMap;
+use input::Input;
+use controller::Controllers;
+use overlay::KeypadOverlay;
+use movie::Movie;
+use cpu::Chip8;
+use display::Display;
+use renderer::Renderer;
+use layout::Layout;
+use filter::Persistence;
+use animation::AnimationRecorder;
+use capture::Capture;
+use script::Script;
+use cheats::Console;
+
+// a 60 hz frame
+const FRAME_DURATION: Duration = Duration::from_nanos(1_000_000_000 / 60);
 
 fn main() {
-    let args: Vec<String> = env::args().collect();
+    let args: Vec<String> = env::args().skip(1).collect();
+    if args.is_empty() {
+        eprint!("{}", options::usage());
+        process::exit(1);
+    }
+    if args.iter().any(|arg| arg == "--help" || arg == "-h") {
+        print!("{}", options::usage());
+        return;
+    }
+    let options = Options::parse(&args).unwrap_or_else(|why| {
+        exit_with_error(&format!("{}, see --help", why))
+    });
+
+    let (rom, rom_path) = rom::load_rom_file(&options.rom_path, options.patch_path.as_deref(), options.hex)
+        .unwrap_or_else(|why| exit_with_error(&why.to_string()));
+    let rom_hash = movie::hash(&rom);
+    let rom_name = config::rom_name(&rom_path);
+
+    let playback = options.play_path.as_ref().map(|path| {
+        let movie = Movie::load(path).unwrap_or_else(|why| exit_with_error(&why));
+        if movie.rom_hash != rom_hash {
+            exit_with_error(&format!("{} was recorded with a different ROM", path));
+        }
+        movie
+    });
+
+    // a movie is played back the way it was recorded
+    let mut cpu = match playback {
+        Some(ref movie) => Chip8::with_seed(movie.seed),
+        None => Chip8::new()
+    };
+    cpu.keypad.set_wait_mode(playback.as_ref().map_or(options.wait_mode, |movie| movie.wait_mode));
+    cpu.load_rom(&rom);
+
+    if options.headless {
+        headless::run(&options, &mut cpu, playback.as_ref().unwrap())
+            .unwrap_or_else(|why| exit_with_error(&why));
+        return;
+    }
+
+    let mut recording = options.record_path.as_ref().map(|_| {
+        Movie::new(cpu.seed(), rom_hash, cpu.keypad.wait_mode())
+    });
+    if options.tui {
+        let keymap = load_keymap(&options, &rom_name).unwrap_or_else(|why| exit_with_error(&why));
+        tui::run(&options, &mut cpu, &keymap, playback, &mut recording)
+            .unwrap_or_else(|why| exit_with_error(&why));
+    } else {
+        run_window(&options, &mut cpu, &rom_name, rom_hash, playback, &mut recording);
+    }
+
+    if let (Some(path), Some(movie)) = (&options.record_path, recording) {
+        movie.save(path).unwrap_or_else(|why| exit_with_error(&why));
+    }
+}
+
+/// Runs the emulator in an SDL window until it is closed. While a movie is
+/// playing its input replaces the user's, afterwards the user takes over.
+fn run_window(options: &Options, cpu: &mut Chip8, rom_name: &str, rom_hash: u64,
+              mut playback: Option<Movie>, recording: &mut Option<Movie>) {
+    let keymap = load_keymap(options, rom_name).unwrap_or_else(|why| exit_with_error(&why));
 
     let sdl_context = sdl2::init().unwrap();
     let video = sdl_context.video().unwrap();
-    let window = video.window("Chip8", 800, 600)
-        .position_centered()
-        .build()
-        .unwrap();
+    let mut window_builder = video.window("Chip8", 800, 600);
+    window_builder.position_centered().resizable();
+    if options.fullscreen {
+        window_builder.fullscreen_desktop();
+    }
+    let window = window_builder.build().unwrap();
     
+    let mut scaling = options.scaling;
+    let mut overlay = KeypadOverlay::new(0, 0);
+    let mut controllers = Controllers::new(sdl_context.game_controller().unwrap());
     let mut event_pump = sdl_context.event_pump().unwrap();
-    let mut canvas = window.into_canvas().build().unwrap();
+    // drawn on the CPU so it runs without a GPU
+    let mut canvas = window.into_canvas().software().build().unwrap();
+    let texture_creator = canvas.texture_creator();
+    let mut renderer = Renderer::new(&texture_creator, &cpu.display, options.palette,
+                                     Persistence::new(options.filter_mode, options.filter_strength),
+                                     options.effects);
     
     let audio_subsystem = sdl_context.audio().unwrap();
-    let mut cpu = cpu::Chip8::new();
     let mut audio = audio::Audio::new(&audio_subsystem);
-    let mut rom = [0; 3583];
-    rom::load_rom_file(&args[1], &mut rom);
-    cpu.load_rom(&rom);
 
-    'main: loop {
-        cpu.cycle();
-        canvas.set_draw_color(Color::RGB(0,0,0));
-        canvas.clear();
-        cpu.display.render(&mut canvas);
-        canvas.present();
+    // the whole session if asked for on the command line, and a clip
+    // started and stopped with F10
+    let mut animation = options.animation_path.as_ref().map(|_| new_animation(options));
+    let mut clip: Option<AnimationRecorder> = None;
+    let mut console = if options.cheats { Some(Console::start(rom_hash)) } else { None };
+    let mut script = options.script_path.as_ref()
+        .map(|path| Script::load(path).unwrap_or_else(|why| exit_with_error(&why)));
+    let mut capture = if options.captures() {
+        let capture = Capture::create(options.video_path.as_deref(),
+                                      options.audio_path.as_deref(),
+                                      &options.palette, options.video_scale);
+        Some(capture.unwrap_or_else(|why| exit_with_error(&why)))
+    } else {
+        None
+    };
 
+    let mut input = Input::new();
+    let mut frame = 0;
+    let mut next_frame = Instant::now();
+
+    'main: loop {
+        let playing = playback.is_some();
         for event in event_pump.poll_iter() {
             match event {
                 Event::Quit {..} => { break 'main },
+                Event::KeyDown {keycode: Some(Keycode::F11), repeat: false, ..} => {
+                    toggle_fullscreen(canvas.window_mut());
+                },
+                Event::KeyDown {keycode: Some(Keycode::F9), repeat: false, ..} => {
+                    scaling = scaling.toggled();
+                },
+                Event::KeyDown {keycode: Some(Keycode::F12), repeat: false, ..} => {
+                    take_screenshot(options, rom_name, &cpu.display, frame);
+                },
+                Event::KeyDown {keycode: Some(Keycode::F10), repeat: false, ..} => {
+                    match clip.take() {
+                        Some(recorder) => {
+                            let path = format!("{}-{}.gif", rom_name, frame);
+                            save_animation(&recorder, &path);
+                        },
+                        None => {
+                            println!("recording clip, press F10 again to stop");
+                            clip = Some(new_animation(options));
+                        }
+                    }
+                },
+                Event::KeyDown {keycode: Some(Keycode::F7), repeat: false, ..} => {
+                    let effects = renderer.effects().next_preset();
+                    renderer.set_effects(effects);
+                },
+                // pads are opened during playback too, so they work once it ends
+                Event::ControllerDeviceAdded {..} |
+                Event::ControllerDeviceRemoved {..} => {
+                    input.handle(|keys| controllers.handle_event(&event, &keymap, keys));
+                },
+                _ if playing => {},
                 Event::KeyDown {..} |
-                Event::KeyUp {..} => { cpu.keypad.handle_event(&event); },
-                _ => {}
+                Event::KeyUp {..} => input.handle(|keys| keymap.handle_event(&event, keys)),
+                Event::MouseButtonDown {..} |
+                Event::MouseButtonUp {..} |
+                Event::FingerDown {..} |
+                Event::FingerUp {..} => {
+                    let size = canvas.window().size();
+                    input.handle(|keys| overlay.handle_event(&event, size, keys));
+                },
+                _ => input.handle(|keys| controllers.handle_event(&event, &keymap, keys))
             }
         }
+
+        let movie_keys = playback.as_ref().map(|movie| movie.frame(frame));
+        match movie_keys {
+            Some(Some(keys)) => cpu.keypad.set_state(keys),
+            Some(None) => {
+                println!("movie finished after {} frames", frame);
+                playback = None;
+                cpu.keypad.set_state(0);
+            },
+            None => {
+                if let Some(keys) = input.next_frame() {
+                    cpu.keypad.set_state(keys);
+                }
+            }
+        }
+        if let Some(ref mut movie) = *recording {
+            movie.record_frame(cpu.keypad.state());
+        }
+
+        if let Some(ref mut console) = console {
+            console.update(&mut cpu.ram);
+        }
+        script::before_frame(&mut script, cpu);
+        cpu.run_frame();
+        if !script::after_frame(&mut script, cpu) {
+            break 'main;
+        }
+        frame += 1;
+        for recorder in animation.iter_mut().chain(clip.iter_mut()) {
+            recorder.capture(&cpu.display);
+        }
+        let beeping = cpu.should_beep();
+        let captured = capture.as_mut().map(|capture| capture.capture_frame(&cpu.display, beeping));
+        if let Some(Err(why)) = captured {
+            println!("WARNING: {}, capture stopped", why);
+            capture = None;
+        }
+
+        let display_size = (cpu.display.width() as u32, cpu.display.height() as u32);
+        let layout = Layout::new(canvas.output_size().unwrap(), display_size,
+                                 overlay::SIZE, scaling);
+        overlay.set_position(layout.panel.0, layout.panel.1);
+
+        let [r, g, b] = options.palette.background();
+        canvas.set_draw_color(Color::RGB(r, g, b));
+        canvas.clear();
+        renderer.update(&mut cpu.display);
+        renderer.draw(&mut canvas, layout.display);
+        overlay.render(&mut canvas, &mut cpu.keypad, &options.palette);
+        canvas.present();
         
-        if cpu.should_beep() {
+        if beeping {
             audio.start_beep();
         } else {
             audio.stop_beep();
         }
 
-        // simulate ~60 hz
-        sleep(Duration::from_millis(2));
+        next_frame += FRAME_DURATION;
+        let now = Instant::now();
+        if next_frame > now {
+            sleep(next_frame - now);
+        } else {
+            // running behind, don't try to catch up
+            next_frame = now;
+        }
+    }
+
+    if let (Some(path), Some(recorder)) = (&options.animation_path, animation) {
+        save_animation(&recorder, path);
+    }
+    if let Some(capture) = capture {
+        let frames = capture.frame_count();
+        match capture.finish() {
+            Ok(()) => println!("captured {} frames", frames),
+            Err(why) => println!("WARNING: {}", why)
+        }
+    }
+}
+
+/// Saves the display to `<rom name>-<frame>.png` in the working directory
+fn take_screenshot(options: &Options, rom_name: &str, display: &Display, frame: usize) {
+    let path = format!("{}-{}.png", rom_name, frame);
+    match screenshot::save_png(Path::new(&path), display, &options.palette,
+                               options.screenshot_scale) {
+        Ok(()) => println!("saved screenshot {}", path),
+        Err(why) => println!("WARNING: {}", why)
+    }
+}
+
+fn new_animation(options: &Options) -> AnimationRecorder {
+    AnimationRecorder::new(options.palette, options.animation_scale, options.dedup)
+}
+
+fn save_animation(recorder: &AnimationRecorder, path: &str) {
+    match recorder.save(Path::new(path)) {
+        Ok(()) => println!("saved {} frames to {}", recorder.frame_count(), path),
+        Err(why) => println!("WARNING: {}", why)
+    }
+}
+
+fn toggle_fullscreen(window: &mut Window) {
+    let fullscreen = match window.fullscreen_state() {
+        FullscreenType::Off => FullscreenType::Desktop,
+        _ => FullscreenType::Off
+    };
+    if let Err(why) = window.set_fullscreen(fullscreen) {
+        println!("WARNING: couldn't toggle fullscreen: {}", why);
+    }
+}
+
+fn exit_with_error(why: &str) -> ! {
+    eprintln!("chip8: {}", why);
+    process::exit(1);
+}
+
+/// Builds the key map from the ROM's controller profile and the chosen key
+/// map file or preset, falling back to `keymap` in the config directory and
+/// then qwerty. Afterwards applies the overrides for the ROM from
+/// `keymaps/<rom name>.keymap` in the config directory if there are any.
+fn load_keymap(options: &Options, rom_name: &str) -> Result<KeyMap, String> {
+    let config_dir = config::config_dir();
+    let default_path = config_dir.as_ref()
+        .map(|dir| dir.join("keymap"))
+        .filter(|path| path.exists());
+
+    let mut keymap = KeyMap::controller_profile(rom_name);
+    match (&options.keymap_path, &options.key_preset, default_path) {
+        (Some(path), _, _) => keymap.apply_file(Path::new(path))?,
+        (None, Some(preset), _) => keymap.apply_preset(preset)?,
+        (None, None, Some(path)) => keymap.apply_file(&path)?,
+        (None, None, None) => keymap.apply_preset("qwerty")?
     }
+
+    if let Some(dir) = config_dir {
+        let rom_keymap = dir.join("keymaps").join(format!("{}.keymap", rom_name));
+        if rom_keymap.exists() {
+            keymap.apply_file(&rom_keymap)?;
+        }
+    }
+
+    Ok(keymap)
 }
@@ -0,0 +1,171 @@
+use std::fs::File;
+use std::io::{Read, Write};
+use std::path::Path;
+
+use keypad::WaitMode;
+
+const MAGIC: &[u8; 4] = b"C8MV";
+const VERSION: u8 = 2;
+// magic, version, seed, rom hash, wait mode and frame count
+const HEADER_SIZE: usize = 4 + 1 + 8 + 8 + 1 + 4;
+
+/// Recorded keypad input. Replaying the frames into a chip8 seeded with
+/// `seed`, waiting for keys with `wait_mode` and running the same ROM
+/// reproduces the recorded session exactly.
+///
+/// Files start with the header `C8MV`, a version byte, the seed, the ROM
+/// hash, the wait mode (0 for release, 1 for press) and the number of
+/// frames, followed by one little endian keypad bitmask per frame.
+#[derive(Debug, PartialEq)]
+pub struct Movie {
+    pub seed: u64,
+    pub rom_hash: u64,
+    pub wait_mode: WaitMode,
+    frames: Vec<u16>
+}
+
+impl Movie {
+    pub fn new(seed: u64, rom_hash: u64, wait_mode: WaitMode) -> Self {
+        Movie {
+            seed,
+            rom_hash,
+            wait_mode,
+            frames: Vec::new()
+        }
+    }
+
+    pub fn record_frame(&mut self, keys: u16) {
+        self.frames.push(keys);
+    }
+
+    /// Keypad state for a frame, None once the movie is over
+    pub fn frame(&self, frame: usize) -> Option<u16> {
+        self.frames.get(frame).cloned()
+    }
+
+    pub fn frame_count(&self) -> usize {
+        self.frames.len()
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(HEADER_SIZE + self.frames.len() * 2);
+        bytes.extend_from_slice(MAGIC);
+        bytes.push(VERSION);
+        bytes.extend_from_slice(&self.seed.to_le_bytes());
+        bytes.extend_from_slice(&self.rom_hash.to_le_bytes());
+        bytes.push(match self.wait_mode {
+            WaitMode::Release => 0,
+            WaitMode::Press => 1
+        });
+        bytes.extend_from_slice(&(self.frames.len() as u32).to_le_bytes());
+        for frame in &self.frames {
+            bytes.extend_from_slice(&frame.to_le_bytes());
+        }
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, String> {
+        if bytes.len() < HEADER_SIZE || &bytes[0..4] != MAGIC {
+            return Err("not a movie file".to_string());
+        }
+        if bytes[4] != VERSION {
+            return Err(format!("unsupported movie version {}", bytes[4]));
+        }
+
+        let mut word = [0; 8];
+        word.copy_from_slice(&bytes[5..13]);
+        let seed = u64::from_le_bytes(word);
+        word.copy_from_slice(&bytes[13..21]);
+        let rom_hash = u64::from_le_bytes(word);
+        let wait_mode = match bytes[21] {
+            0 => WaitMode::Release,
+            1 => WaitMode::Press,
+            mode => return Err(format!("unknown wait mode {}", mode))
+        };
+        let mut count = [0; 4];
+        count.copy_from_slice(&bytes[22..26]);
+        let count = u32::from_le_bytes(count) as usize;
+
+        let data = &bytes[HEADER_SIZE..];
+        if data.len() != count * 2 {
+            return Err("movie file is truncated".to_string());
+        }
+        let frames = data.chunks(2)
+            .map(|frame| u16::from_le_bytes([frame[0], frame[1]]))
+            .collect();
+
+        Ok(Movie { seed, rom_hash, wait_mode, frames })
+    }
+
+    pub fn load(path: &str) -> Result<Self, String> {
+        let mut bytes = Vec::new();
+        File::open(Path::new(path))
+            .and_then(|mut file| file.read_to_end(&mut bytes))
+            .map_err(|why| format!("couldn't read {}: {}", path, why))?;
+        Movie::from_bytes(&bytes).map_err(|why| format!("{}: {}", path, why))
+    }
+
+    pub fn save(&self, path: &str) -> Result<(), String> {
+        File::create(Path::new(path))
+            .and_then(|mut file| file.write_all(&self.to_bytes()))
+            .map_err(|why| format!("couldn't write {}: {}", path, why))
+    }
+}
+
+/// FNV-1a hash, used to make sure a movie is played back with the ROM it
+/// was recorded with and to compare the display at the end of a replay
+pub fn hash(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &byte| {
+        (hash ^ byte as u64).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let mut movie = Movie::new(42, hash(b"rom"), WaitMode::Press);
+        movie.record_frame(0);
+        movie.record_frame(0x1001);
+        movie.record_frame(0xFFFF);
+
+        let loaded = Movie::from_bytes(&movie.to_bytes()).unwrap();
+        assert_eq!(loaded, movie);
+        assert_eq!(loaded.wait_mode, WaitMode::Press);
+        assert_eq!(loaded.frame_count(), 3);
+        assert_eq!(loaded.frame(1), Some(0x1001));
+        assert_eq!(loaded.frame(3), None);
+    }
+
+    #[test]
+    fn test_from_bytes_errors() {
+        let bytes = Movie::new(1, 2, WaitMode::Release).to_bytes();
+
+        assert!(Movie::from_bytes(b"C8M").is_err());
+        assert!(Movie::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+
+        let mut wrong_magic = bytes.clone();
+        wrong_magic[0] = b'X';
+        assert!(Movie::from_bytes(&wrong_magic).is_err());
+
+        let mut old_version = bytes.clone();
+        old_version[4] = 1;
+        assert!(Movie::from_bytes(&old_version).is_err());
+
+        let mut wrong_mode = bytes.clone();
+        wrong_mode[21] = 2;
+        assert!(Movie::from_bytes(&wrong_mode).is_err());
+
+        let mut extra_frame = bytes.clone();
+        extra_frame.extend_from_slice(&[1, 0]);
+        assert!(Movie::from_bytes(&extra_frame).is_err());
+    }
+
+    #[test]
+    fn test_hash() {
+        assert_eq!(hash(b""), 0xcbf2_9ce4_8422_2325);
+        assert_ne!(hash(b"pong"), hash(b"tetris"));
+    }
+}
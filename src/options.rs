@@ -0,0 +1,425 @@
+use keymap::KeyMap;
+use keypad::WaitMode;
+use layout::Scaling;
+use palette::{self, Palette};
+use filter::FilterMode;
+use postfx::Effects;
+use termgfx::Graphics;
+
+const USAGE: &str = "\
+usage: chip8 [OPTIONS] ROM
+
+ROM is a .ch8, .sc8 or .xo8 file, a zip archive holding one, a .hex or .txt
+hex dump, or - to read it from stdin.
+
+input:
+  --keys PRESET              key layout: {presets}
+  --keymap FILE              key map file, see src/keymap.rs
+  --wait-on-press            Fx0A returns on press instead of release
+display:
+  --scaling MODE             integer or fractional
+  --fullscreen               start fullscreen, F11 toggles it
+  --theme NAME               colour theme: {themes}
+  --palette COLORS           comma separated colours, e.g. #000000,#ffffff
+  --bg COLOR, --fg COLOR     background and foreground colour
+  --persistence MODE         off, blend or decay, against flicker
+  --persistence-strength N   between 0 and 1
+  --effects LIST             comma separated post processing effects
+  --tui                      run in the terminal
+  --graphics KIND            terminal graphics: blocks, sixel or kitty
+  --graphics-scale N         pixels per chip8 pixel for sixel and kitty
+movies and captures:
+  --record FILE              record the input to a movie
+  --play FILE                play a movie back
+  --headless                 play a movie back without a window
+  --screenshot FILE          save the last frame of a headless run
+  --screenshot-scale N
+  --animation FILE           save a gif or apng of the session
+  --animation-scale N
+  --dedup                    merge identical animation frames
+  --video FILE               save the session as y4m video
+  --audio FILE               save the session as wav audio
+  --video-scale N
+tools:
+  --script FILE              run a Rhai script alongside, see src/script.rs
+  --cheats                   cheat console on stdin, see src/cheats.rs
+  --patch FILE               apply an IPS or BPS patch to the ROM
+  --hex                      read the ROM as a hex dump, e.g. from xxd
+  --help                     show this message
+";
+
+/// The help text, listing the built in key layouts and themes
+pub fn usage() -> String {
+    USAGE.replace("{presets}", &one_of(KeyMap::preset_names()))
+        .replace("{themes}", &one_of(Palette::theme_names()))
+}
+
+// "a, b or c"
+fn one_of<'a, I: Iterator<Item = &'a str>>(names: I) -> String {
+    let mut names: Vec<&str> = names.collect();
+    let last = names.pop().unwrap_or_default();
+    if names.is_empty() {
+        last.to_string()
+    } else {
+        format!("{} or {}", names.join(", "), last)
+    }
+}
+
+/// Settings picked from the command line
+pub struct Options {
+    pub rom_path: String,
+    pub wait_mode: WaitMode,
+    pub key_preset: Option<String>,
+    pub keymap_path: Option<String>,
+    pub record_path: Option<String>,
+    pub play_path: Option<String>,
+    pub headless: bool,
+    pub scaling: Scaling,
+    pub fullscreen: bool,
+    pub palette: Palette,
+    pub filter_mode: FilterMode,
+    pub filter_strength: f32,
+    pub effects: Effects,
+    pub screenshot_path: Option<String>,
+    pub screenshot_scale: usize,
+    pub animation_path: Option<String>,
+    pub animation_scale: usize,
+    pub dedup: bool,
+    pub video_path: Option<String>,
+    pub audio_path: Option<String>,
+    pub video_scale: usize,
+    pub tui: bool,
+    pub graphics: Graphics,
+    pub graphics_scale: usize,
+    pub script_path: Option<String>,
+    pub cheats: bool,
+    pub patch_path: Option<String>,
+    pub hex: bool
+}
+
+impl Options {
+    /// Parses the arguments following the program name
+    pub fn parse(args: &[String]) -> Result<Self, String> {
+        let mut rom_path = None;
+        let mut wait_mode = WaitMode::Release;
+        let mut key_preset = None;
+        let mut keymap_path = None;
+        let mut record_path = None;
+        let mut play_path = None;
+        let mut headless = false;
+        let mut scaling = Scaling::Integer;
+        let mut fullscreen = false;
+        let mut theme = None;
+        let mut colors = None;
+        let mut background = None;
+        let mut foreground = None;
+        let mut filter_mode = FilterMode::Off;
+        let mut filter_strength = None;
+        let mut effects = Effects::default();
+        let mut screenshot_path = None;
+        let mut screenshot_scale = 1;
+        let mut animation_path = None;
+        let mut animation_scale = 4;
+        let mut dedup = false;
+        let mut video_path = None;
+        let mut audio_path = None;
+        let mut video_scale = 1;
+        let mut tui = false;
+        let mut graphics = None;
+        let mut graphics_scale = 6;
+        let mut script_path = None;
+        let mut cheats = false;
+        let mut patch_path = None;
+        let mut hex = false;
+
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--wait-on-press" => wait_mode = WaitMode::Press,
+                "--keys" => key_preset = Some(Options::value(arg, args.next())?),
+                "--keymap" => keymap_path = Some(Options::value(arg, args.next())?),
+                "--record" => record_path = Some(Options::value(arg, args.next())?),
+                "--play" => play_path = Some(Options::value(arg, args.next())?),
+                "--headless" => headless = true,
+                "--tui" => tui = true,
+                "--graphics" => {
+                    let name = Options::value(arg, args.next())?;
+                    graphics = Some(Graphics::from_name(&name)
+                        .ok_or_else(|| format!("unknown terminal graphics {}", name))?);
+                },
+                "--graphics-scale" => graphics_scale = Options::positive(arg, args.next())?,
+                "--scaling" => {
+                    let name = Options::value(arg, args.next())?;
+                    scaling = Scaling::from_name(&name)
+                        .ok_or_else(|| format!("unknown scaling {}", name))?;
+                },
+                "--fullscreen" => fullscreen = true,
+                "--theme" => theme = Some(Options::value(arg, args.next())?),
+                "--palette" => colors = Some(Options::value(arg, args.next())?),
+                "--bg" => background = Some(Options::value(arg, args.next())?),
+                "--fg" => foreground = Some(Options::value(arg, args.next())?),
+                "--persistence" => {
+                    let name = Options::value(arg, args.next())?;
+                    filter_mode = FilterMode::from_name(&name)
+                        .ok_or_else(|| format!("unknown persistence mode {}", name))?;
+                },
+                "--effects" => effects = Effects::parse(&Options::value(arg, args.next())?)?,
+                "--screenshot" => screenshot_path = Some(Options::value(arg, args.next())?),
+                "--screenshot-scale" => screenshot_scale = Options::positive(arg, args.next())?,
+                "--animation" => animation_path = Some(Options::value(arg, args.next())?),
+                "--animation-scale" => animation_scale = Options::positive(arg, args.next())?,
+                "--dedup" => dedup = true,
+                "--script" => script_path = Some(Options::value(arg, args.next())?),
+                "--cheats" => cheats = true,
+                "--patch" => patch_path = Some(Options::value(arg, args.next())?),
+                "--hex" => hex = true,
+                "--video" => video_path = Some(Options::value(arg, args.next())?),
+                "--audio" => audio_path = Some(Options::value(arg, args.next())?),
+                "--video-scale" => video_scale = Options::positive(arg, args.next())?,
+                "--persistence-strength" => {
+                    let value = Options::value(arg, args.next())?;
+                    filter_strength = match value.parse::<f32>() {
+                        Ok(strength) if (0.0..=1.0).contains(&strength) => Some(strength),
+                        _ => return Err(format!("persistence strength {} isn't between 0 and 1", value))
+                    };
+                },
+                flag if flag.starts_with("--") => {
+                    return Err(format!("unknown option {}", flag));
+                },
+                path => rom_path = Some(path.to_string())
+            }
+        }
+
+        if record_path.is_some() && play_path.is_some() {
+            return Err("can't record and play a movie at the same time".to_string());
+        }
+        if headless && play_path.is_none() {
+            return Err("--headless needs a movie to --play".to_string());
+        }
+        if tui && headless {
+            return Err("--tui and --headless can't be used together".to_string());
+        }
+        if cheats && (tui || headless) {
+            return Err("--cheats reads commands from the terminal, so only works in a window".to_string());
+        }
+        if cheats && (record_path.is_some() || play_path.is_some()) {
+            return Err("--cheats would desync movies, so can't be used with --record or --play".to_string());
+        }
+        if cheats && rom_path.as_deref() == Some("-") {
+            return Err("--cheats reads commands from stdin, so the ROM can't come from it".to_string());
+        }
+        if graphics.is_some() && !tui {
+            return Err("--graphics only works with --tui".to_string());
+        }
+        if tui && (animation_path.is_some() || video_path.is_some() || audio_path.is_some()) {
+            return Err("--animation, --video and --audio only work in a window or --headless".to_string());
+        }
+        if screenshot_path.is_some() && !headless {
+            return Err("--screenshot only works with --headless, press F12 instead".to_string());
+        }
+
+        // single colours win over the palette, which wins over the theme
+        let mut palette = match theme {
+            Some(name) => Palette::theme(&name).ok_or_else(|| format!("unknown theme {}", name))?,
+            None => Palette::default()
+        };
+        if let Some(colors) = colors {
+            palette.set_colors(&colors)?;
+        }
+        if let Some(background) = background {
+            palette.colors[0] = palette::parse_color(&background)?;
+        }
+        if let Some(foreground) = foreground {
+            palette.colors[1] = palette::parse_color(&foreground)?;
+        }
+
+        // phosphor decay needs to keep more of each frame than blending does
+        let filter_strength = filter_strength.unwrap_or(match filter_mode {
+            FilterMode::Decay => 0.7,
+            _ => 0.5
+        });
+
+        match rom_path {
+            Some(rom_path) => Ok(Options {
+                rom_path,
+                wait_mode,
+                key_preset,
+                keymap_path,
+                record_path,
+                play_path,
+                headless,
+                scaling,
+                fullscreen,
+                palette,
+                filter_mode,
+                filter_strength,
+                effects,
+                screenshot_path,
+                screenshot_scale,
+                animation_path,
+                animation_scale,
+                dedup,
+                video_path,
+                audio_path,
+                video_scale,
+                tui,
+                graphics: graphics.unwrap_or(Graphics::Blocks),
+                graphics_scale,
+                script_path,
+                cheats,
+                patch_path,
+                hex
+            }),
+            None => Err("no ROM file given".to_string())
+        }
+    }
+
+    /// Whether a y4m or wav capture was asked for
+    pub fn captures(&self) -> bool {
+        self.video_path.is_some() || self.audio_path.is_some()
+    }
+
+    fn value(flag: &str, value: Option<&String>) -> Result<String, String> {
+        value.cloned().ok_or_else(|| format!("{} needs a value", flag))
+    }
+
+    fn positive(flag: &str, value: Option<&String>) -> Result<usize, String> {
+        let value = Options::value(flag, value)?;
+        match value.parse::<usize>() {
+            Ok(number) if number > 0 => Ok(number),
+            _ => Err(format!("{} {} isn't a positive number", flag, value))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn test_usage() {
+        let usage = usage();
+        assert!(usage.contains("key layout: qwerty, azerty, dvorak or numpad\n"));
+        assert!(usage.contains("colour theme: orange, green, lcd, amber or contrast\n"));
+    }
+
+    #[test]
+    fn test_parse() {
+        let options = Options::parse(&args(&["pong.ch8"])).unwrap();
+        assert_eq!(options.rom_path, "pong.ch8");
+        assert_eq!(options.wait_mode, WaitMode::Release);
+        assert_eq!(options.key_preset, None);
+        assert_eq!(options.keymap_path, None);
+
+        let options = Options::parse(&args(&[
+            "--wait-on-press", "--keys", "dvorak", "--keymap", "my.keymap", "pong.ch8"
+        ])).unwrap();
+        assert_eq!(options.wait_mode, WaitMode::Press);
+        assert_eq!(options.key_preset, Some("dvorak".to_string()));
+        assert_eq!(options.keymap_path, Some("my.keymap".to_string()));
+
+        let options = Options::parse(&args(&["--headless", "--play", "bug.c8m", "pong.ch8"])).unwrap();
+        assert!(options.headless);
+        assert_eq!(options.play_path, Some("bug.c8m".to_string()));
+        assert_eq!(options.record_path, None);
+
+        let options = Options::parse(&args(&["--scaling", "fractional", "--fullscreen", "pong.ch8"])).unwrap();
+        assert_eq!(options.scaling, Scaling::Fractional);
+        assert!(options.fullscreen);
+
+        let options = Options::parse(&args(&["--fg", "#ffffff", "--theme", "lcd", "pong.ch8"])).unwrap();
+        assert_eq!(options.palette.background(), Palette::theme("lcd").unwrap().background());
+        assert_eq!(options.palette.foreground(), [255, 255, 255]);
+
+        let options = Options::parse(&args(&["--persistence", "decay", "pong.ch8"])).unwrap();
+        assert_eq!(options.filter_mode, FilterMode::Decay);
+        assert_eq!(options.filter_strength, 0.7);
+
+        let options = Options::parse(&args(&[
+            "--persistence", "blend", "--persistence-strength", "0.25", "pong.ch8"
+        ])).unwrap();
+        assert_eq!(options.filter_mode, FilterMode::Blend);
+        assert_eq!(options.filter_strength, 0.25);
+
+        let options = Options::parse(&args(&["--effects", "grid,glow", "pong.ch8"])).unwrap();
+        assert!(options.effects.grid && options.effects.glow);
+
+        let options = Options::parse(&args(&[
+            "--headless", "--play", "bug.c8m", "--screenshot", "end.png", "--screenshot-scale", "4",
+            "pong.ch8"
+        ])).unwrap();
+        assert_eq!(options.screenshot_path, Some("end.png".to_string()));
+        assert_eq!(options.screenshot_scale, 4);
+
+        let options = Options::parse(&args(&["--animation", "clip.gif", "--dedup", "pong.ch8"])).unwrap();
+        assert_eq!(options.animation_path, Some("clip.gif".to_string()));
+        assert_eq!(options.animation_scale, 4);
+        assert!(options.dedup);
+
+        let options = Options::parse(&args(&[
+            "--video", "run.y4m", "--audio", "run.wav", "--video-scale", "2", "pong.ch8"
+        ])).unwrap();
+        assert_eq!(options.video_path, Some("run.y4m".to_string()));
+        assert_eq!(options.audio_path, Some("run.wav".to_string()));
+        assert_eq!(options.video_scale, 2);
+
+        let options = Options::parse(&args(&["--tui", "pong.ch8"])).unwrap();
+        assert!(options.tui);
+        assert_eq!(options.graphics, Graphics::Blocks);
+
+        let options = Options::parse(&args(&["--tui", "--graphics", "sixel", "pong.ch8"])).unwrap();
+        assert_eq!(options.graphics, Graphics::Sixel);
+        assert_eq!(options.graphics_scale, 6);
+
+        let options = Options::parse(&args(&["--script", "bot.rhai", "pong.ch8"])).unwrap();
+        assert_eq!(options.script_path, Some("bot.rhai".to_string()));
+        assert!(!options.cheats);
+
+        let options = Options::parse(&args(&["--cheats", "tetris.ch8"])).unwrap();
+        assert!(options.cheats);
+        assert_eq!(options.patch_path, None);
+        assert!(!options.hex);
+
+        let options = Options::parse(&args(&["--patch", "fix.bps", "tetris.ch8"])).unwrap();
+        assert_eq!(options.patch_path, Some("fix.bps".to_string()));
+
+        let options = Options::parse(&args(&["--hex", "-"])).unwrap();
+        assert!(options.hex);
+        assert_eq!(options.rom_path, "-");
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert!(Options::parse(&args(&[])).is_err());
+        assert!(Options::parse(&args(&["--bogus", "pong.ch8"])).is_err());
+        assert!(Options::parse(&args(&["pong.ch8", "--keys"])).is_err());
+        assert!(Options::parse(&args(&["pong.ch8", "--script"])).is_err());
+        assert!(Options::parse(&args(&["--headless", "pong.ch8"])).is_err());
+        assert!(Options::parse(&args(&["--tui", "--headless", "--play", "a.c8m", "pong.ch8"])).is_err());
+        assert!(Options::parse(&args(&["--scaling", "huge", "pong.ch8"])).is_err());
+        assert!(Options::parse(&args(&["--theme", "plaid", "pong.ch8"])).is_err());
+        assert!(Options::parse(&args(&["--bg", "black", "pong.ch8"])).is_err());
+        assert!(Options::parse(&args(&["--persistence", "smear", "pong.ch8"])).is_err());
+        assert!(Options::parse(&args(&["--persistence-strength", "2", "pong.ch8"])).is_err());
+        assert!(Options::parse(&args(&["--effects", "vhs", "pong.ch8"])).is_err());
+        assert!(Options::parse(&args(&["--screenshot", "end.png", "pong.ch8"])).is_err());
+        assert!(Options::parse(&args(&["--screenshot-scale", "0", "pong.ch8"])).is_err());
+        assert!(Options::parse(&args(&["--animation-scale", "x", "pong.ch8"])).is_err());
+        assert!(Options::parse(&args(&["--video-scale", "0", "pong.ch8"])).is_err());
+        assert!(Options::parse(&args(&["--graphics", "kitty", "pong.ch8"])).is_err());
+        assert!(Options::parse(&args(&["--tui", "--cheats", "pong.ch8"])).is_err());
+        assert!(Options::parse(&args(&["--cheats", "-"])).is_err());
+        assert!(Options::parse(&args(&["--cheats", "--record", "a.c8m", "pong.ch8"])).is_err());
+        assert!(Options::parse(&args(&["--cheats", "--play", "a.c8m", "pong.ch8"])).is_err());
+        assert!(Options::parse(&args(&["--tui", "--graphics", "ascii", "pong.ch8"])).is_err());
+        assert!(Options::parse(&args(&["--tui", "--animation", "a.gif", "pong.ch8"])).is_err());
+        assert!(Options::parse(&args(&["--tui", "--video", "a.y4m", "pong.ch8"])).is_err());
+        assert!(Options::parse(&args(&["--tui", "--audio", "a.wav", "pong.ch8"])).is_err());
+        assert!(Options::parse(&args(&["--tui", "--screenshot", "a.png", "pong.ch8"])).is_err());
+        assert!(Options::parse(&args(&["--play", "a.c8m", "--record", "b.c8m", "pong.ch8"])).is_err());
+    }
+}
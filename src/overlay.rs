@@ -0,0 +1,183 @@
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use sdl2::event::Event;
+use sdl2::mouse::MouseButton;
+use sdl2::pixels::Color;
+use sdl2::rect::Rect;
+use sdl2::render::Canvas;
+use sdl2::video::Window;
+
+use fonts::FONTS;
+use keypad::Keypad;
+use palette::Palette;
+
+const KEY_SIZE: u32 = 34;
+const KEY_GAP: u32 = 4;
+/// Width and height of the whole keypad
+pub const SIZE: u32 = 4 * KEY_SIZE + 3 * KEY_GAP;
+// font sprites are 4x5, drawn at this scale for the key labels
+const LABEL_SCALE: u32 = 4;
+// how long a key stays highlighted after the program polled it
+const POLL_HIGHLIGHT: u64 = 250;
+// SDL sends mouse events for touches too, with this as the mouse id
+const TOUCH_MOUSE_ID: u32 = 0xFFFF_FFFF;
+
+// keys laid out like the COSMAC VIP's hex keypad
+const LAYOUT: [[usize; 4]; 4] = [
+    [0x1, 0x2, 0x3, 0xC],
+    [0x4, 0x5, 0x6, 0xD],
+    [0x7, 0x8, 0x9, 0xE],
+    [0xA, 0x0, 0xB, 0xF]
+];
+
+/// Clickable hex keypad drawn next to the display. Keys light up while held
+/// and are outlined while the program is polling them.
+pub struct KeypadOverlay {
+    x: i32,
+    y: i32,
+    mouse_key: Option<usize>,
+    finger_keys: HashMap<i64, usize>,
+    polled_at: [Option<Instant>; 16]
+}
+
+impl KeypadOverlay {
+    /// Creates an overlay with its top left corner at (x, y)
+    pub fn new(x: i32, y: i32) -> Self {
+        KeypadOverlay {
+            x,
+            y,
+            mouse_key: None,
+            finger_keys: HashMap::new(),
+            polled_at: [None; 16]
+        }
+    }
+
+    /// Moves the top left corner to (x, y)
+    pub fn set_position(&mut self, x: i32, y: i32) {
+        self.x = x;
+        self.y = y;
+    }
+
+    fn key_rect(&self, row: usize, column: usize) -> Rect {
+        let step = (KEY_SIZE + KEY_GAP) as i32;
+        Rect::new(self.x + column as i32 * step, self.y + row as i32 * step, KEY_SIZE, KEY_SIZE)
+    }
+
+    /// Returns the key under a point in window coordinates
+    pub fn key_at(&self, x: i32, y: i32) -> Option<usize> {
+        for (row, keys) in LAYOUT.iter().enumerate() {
+            for (column, &key) in keys.iter().enumerate() {
+                if self.key_rect(row, column).contains_point((x, y)) {
+                    return Some(key);
+                }
+            }
+        }
+        None
+    }
+
+    /// Turns clicks and touches on the overlay into key presses. Needs the
+    /// window size since touch positions are normalized.
+    pub fn handle_event(&mut self, event: &Event, window_size: (u32, u32), keypad: &mut Keypad) {
+        match *event {
+            Event::MouseButtonDown {which, mouse_btn: MouseButton::Left, x, y, ..}
+                    if which != TOUCH_MOUSE_ID => {
+                self.mouse_key = self.key_at(x, y);
+                if let Some(key) = self.mouse_key {
+                    keypad.press(key);
+                }
+            },
+            Event::MouseButtonUp {which, mouse_btn: MouseButton::Left, ..}
+                    if which != TOUCH_MOUSE_ID => {
+                if let Some(key) = self.mouse_key.take() {
+                    keypad.release(key);
+                }
+            },
+            Event::FingerDown {finger_id, x, y, ..} => {
+                let x = (x * window_size.0 as f32) as i32;
+                let y = (y * window_size.1 as f32) as i32;
+                if let Some(key) = self.key_at(x, y) {
+                    self.finger_keys.insert(finger_id, key);
+                    keypad.press(key);
+                }
+            },
+            Event::FingerUp {finger_id, ..} => {
+                if let Some(key) = self.finger_keys.remove(&finger_id) {
+                    keypad.release(key);
+                }
+            },
+            _ => {}
+        }
+    }
+
+    /// Draws the keypad, highlighting with the palette's foreground colour
+    pub fn render(&mut self, canvas: &mut Canvas<Window>, keypad: &mut Keypad, palette: &Palette) {
+        let [r, g, b] = palette.foreground();
+        let highlight = Color::RGB(r, g, b);
+
+        let now = Instant::now();
+        for (key, &polled) in keypad.take_polled().iter().enumerate() {
+            if polled {
+                self.polled_at[key] = Some(now);
+            }
+        }
+
+        for (row, keys) in LAYOUT.iter().enumerate() {
+            for (column, &key) in keys.iter().enumerate() {
+                let rect = self.key_rect(row, column);
+                let polling = self.polled_at[key].is_some_and(|at| {
+                    now.duration_since(at) < Duration::from_millis(POLL_HIGHLIGHT)
+                });
+
+                let (face, label) = if keypad.is_pressed(key) {
+                    (highlight, Color::RGB(0, 0, 0))
+                } else {
+                    (Color::RGB(60, 60, 60), Color::RGB(200, 200, 200))
+                };
+                canvas.set_draw_color(face);
+                let _ = canvas.fill_rect(rect);
+
+                if polling {
+                    canvas.set_draw_color(highlight);
+                    let _ = canvas.draw_rect(rect);
+                }
+
+                canvas.set_draw_color(label);
+                KeypadOverlay::draw_label(canvas, rect, key);
+            }
+        }
+    }
+
+    // draws the hex digit using the chip8 font
+    fn draw_label(canvas: &mut Canvas<Window>, rect: Rect, key: usize) {
+        let left = rect.x() + ((KEY_SIZE - 4 * LABEL_SCALE) / 2) as i32;
+        let top = rect.y() + ((KEY_SIZE - 5 * LABEL_SCALE) / 2) as i32;
+
+        for (row, &line) in FONTS[key * 5..key * 5 + 5].iter().enumerate() {
+            for column in 0..4 {
+                if line & (0x80 >> column) != 0 {
+                    let x = left + (column * LABEL_SCALE) as i32;
+                    let y = top + (row as u32 * LABEL_SCALE) as i32;
+                    let _ = canvas.fill_rect(Rect::new(x, y, LABEL_SCALE, LABEL_SCALE));
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_key_at() {
+        let overlay = KeypadOverlay::new(100, 10);
+
+        assert_eq!(overlay.key_at(100, 10), Some(1));
+        assert_eq!(overlay.key_at(100 + 3 * 38 + 5, 10 + 5), Some(0xC));
+        assert_eq!(overlay.key_at(100 + 38 + 1, 10 + 3 * 38 + 1), Some(0));
+        // gap between keys
+        assert_eq!(overlay.key_at(100 + 35, 10), None);
+        assert_eq!(overlay.key_at(99, 10), None);
+    }
+}
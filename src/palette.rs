@@ -0,0 +1,139 @@
+pub type Rgb = [u8; 3];
+
+/// Colours the display is drawn with. Index 0 is the background and 1 the
+/// foreground. Displays with two bit planes use 2 for pixels set only in
+/// the second plane and 3 for pixels set in both.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Palette {
+    pub colors: [Rgb; 4]
+}
+
+// name, background, foreground, second plane, both planes
+const THEMES: [(&str, Palette); 5] = [
+    ("orange", Palette { colors: [[0, 0, 0], [255, 165, 0], [140, 70, 0], [255, 230, 160]] }),
+    ("green", Palette { colors: [[5, 20, 8], [60, 255, 110], [20, 120, 50], [190, 255, 210]] }),
+    ("lcd", Palette { colors: [[155, 188, 15], [15, 56, 15], [48, 98, 48], [139, 172, 15]] }),
+    ("amber", Palette { colors: [[20, 10, 0], [255, 176, 0], [150, 90, 0], [255, 220, 140]] }),
+    ("contrast", Palette { colors: [[0, 0, 0], [255, 255, 255], [0, 160, 255], [255, 255, 0]] })
+];
+
+impl Default for Palette {
+    fn default() -> Self {
+        THEMES[0].1
+    }
+}
+
+impl Palette {
+    /// Returns a built in theme: orange, green, lcd, amber or contrast
+    pub fn theme(name: &str) -> Option<Self> {
+        THEMES.iter()
+            .find(|&&(theme, _)| theme == name)
+            .map(|&(_, palette)| palette)
+    }
+
+    /// Names of the built in themes
+    pub fn theme_names() -> impl Iterator<Item = &'static str> {
+        THEMES.iter().map(|&(name, _)| name)
+    }
+
+    /// Parses a comma separated list of up to four colours, replacing the
+    /// palette's colours from the background onwards
+    pub fn set_colors(&mut self, list: &str) -> Result<(), String> {
+        let colors: Vec<&str> = list.split(',').collect();
+        if colors.len() > 4 {
+            return Err(format!("a palette has at most 4 colours, got {}", colors.len()));
+        }
+        for (index, color) in colors.iter().enumerate() {
+            self.colors[index] = parse_color(color.trim())?;
+        }
+        Ok(())
+    }
+
+    pub fn background(&self) -> Rgb {
+        self.colors[0]
+    }
+
+    pub fn foreground(&self) -> Rgb {
+        self.colors[1]
+    }
+
+    /// Colour of a pixel as RGBA bytes
+    pub fn rgba(&self, index: usize) -> [u8; 4] {
+        let [r, g, b] = self.colors[index];
+        [r, g, b, 255]
+    }
+
+    /// Mixes the background and foreground, 0 being all background and 1
+    /// all foreground
+    pub fn blend(&self, level: f32) -> [u8; 4] {
+        if level <= 0.0 {
+            return self.rgba(0);
+        }
+        if level >= 1.0 {
+            return self.rgba(1);
+        }
+
+        let (background, foreground) = (self.background(), self.foreground());
+        let mix = |channel: usize| {
+            let from = background[channel] as f32;
+            let to = foreground[channel] as f32;
+            (from + (to - from) * level).round() as u8
+        };
+        [mix(0), mix(1), mix(2), 255]
+    }
+}
+
+/// Parses a colour written as `RRGGBB` or `#RRGGBB`
+pub fn parse_color(text: &str) -> Result<Rgb, String> {
+    let hex = text.trim_start_matches('#');
+    if hex.len() != 6 || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(format!("{} is not a colour, expected #RRGGBB", text));
+    }
+
+    let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).unwrap();
+    Ok([channel(0), channel(2), channel(4)])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_theme() {
+        assert_eq!(Palette::theme("orange"), Some(Palette::default()));
+        assert_eq!(Palette::theme("lcd").unwrap().foreground(), [15, 56, 15]);
+        assert_eq!(Palette::theme("plaid"), None);
+    }
+
+    #[test]
+    fn test_blend() {
+        let mut palette = Palette::default();
+        palette.set_colors("#000000,#ff8040").unwrap();
+
+        assert_eq!(palette.blend(0.0), palette.rgba(0));
+        assert_eq!(palette.blend(1.0), palette.rgba(1));
+        assert_eq!(palette.blend(0.5), [128, 64, 32, 255]);
+    }
+
+    #[test]
+    fn test_parse_color() {
+        assert_eq!(parse_color("#ff8000"), Ok([255, 128, 0]));
+        assert_eq!(parse_color("0A0b0C"), Ok([10, 11, 12]));
+        assert!(parse_color("#fff").is_err());
+        assert!(parse_color("#gg0000").is_err());
+    }
+
+    #[test]
+    fn test_set_colors() {
+        let mut palette = Palette::default();
+        palette.set_colors("#101010, #f0f0f0").unwrap();
+        assert_eq!(palette.background(), [16, 16, 16]);
+        assert_eq!(palette.foreground(), [240, 240, 240]);
+        assert_eq!(palette.colors[2], Palette::default().colors[2]);
+
+        palette.set_colors("000000,111111,222222,333333").unwrap();
+        assert_eq!(palette.rgba(3), [0x33, 0x33, 0x33, 255]);
+
+        assert!(palette.set_colors("000000,111111,222222,333333,444444").is_err());
+    }
+}
@@ -0,0 +1,265 @@
+//! IPS and BPS patches, for fixes and translations distributed without the
+//! ROMs they apply to
+
+const IPS_MAGIC: &[u8] = b"PATCH";
+const IPS_END: &[u8] = b"EOF";
+const BPS_MAGIC: &[u8] = b"BPS1";
+// source, target and patch checksums
+const BPS_FOOTER_SIZE: usize = 12;
+
+/// Applies an IPS or BPS patch, told apart by their magic numbers
+pub fn apply(patch: &[u8], rom: &[u8]) -> Result<Vec<u8>, String> {
+    if patch.starts_with(IPS_MAGIC) {
+        apply_ips(patch, rom)
+    } else if patch.starts_with(BPS_MAGIC) {
+        apply_bps(patch, rom)
+    } else {
+        Err("not an IPS or BPS patch".to_string())
+    }
+}
+
+/// The CRC-32 used by zip and png, which BPS checksums with
+pub fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = 0xFFFF_FFFF;
+    for &byte in bytes {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            let mask = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (0xEDB8_8320 & mask);
+        }
+    }
+    !crc
+}
+
+/// Reads the big endian numbers and blocks of a patch, failing instead of
+/// panicking on truncated files
+struct Reader<'a> {
+    bytes: &'a [u8],
+    position: usize
+}
+
+impl<'a> Reader<'a> {
+    fn new(bytes: &'a [u8], position: usize) -> Self {
+        Reader { bytes, position }
+    }
+
+    fn take(&mut self, count: usize) -> Result<&'a [u8], String> {
+        let end = self.position + count;
+        if end > self.bytes.len() {
+            return Err("patch ends early".to_string());
+        }
+        let slice = &self.bytes[self.position..end];
+        self.position = end;
+        Ok(slice)
+    }
+
+    fn number(&mut self, size: usize) -> Result<usize, String> {
+        Ok(self.take(size)?.iter().fold(0, |number, &byte| number << 8 | byte as usize))
+    }
+
+    // BPS numbers, 7 bits a byte with the last byte's top bit set
+    fn varint(&mut self) -> Result<usize, String> {
+        let mut number = 0usize;
+        let mut shift = 1usize;
+        loop {
+            let byte = self.take(1)?[0] as usize;
+            number = number.checked_add((byte & 0x7F).checked_mul(shift).ok_or("number too large")?)
+                .ok_or("number too large")?;
+            if byte & 0x80 != 0 {
+                return Ok(number);
+            }
+            shift = shift.checked_shl(7).filter(|&shift| shift != 0).ok_or("number too large")?;
+            number = number.checked_add(shift).ok_or("number too large")?;
+        }
+    }
+}
+
+fn apply_ips(patch: &[u8], rom: &[u8]) -> Result<Vec<u8>, String> {
+    let mut target = rom.to_vec();
+    let mut reader = Reader::new(patch, IPS_MAGIC.len());
+    loop {
+        if reader.take(IPS_END.len())? == IPS_END {
+            break;
+        }
+        reader.position -= IPS_END.len();
+
+        let offset = reader.number(3)?;
+        let data = match reader.number(2)? {
+            // a size of 0 repeats one byte
+            0 => {
+                let count = reader.number(2)?;
+                vec![reader.take(1)?[0]; count]
+            },
+            size => reader.take(size)?.to_vec()
+        };
+
+        let end = offset + data.len();
+        if target.len() < end {
+            target.resize(end, 0);
+        }
+        target[offset..end].copy_from_slice(&data);
+    }
+
+    // an extension puts the size to truncate to after the end marker
+    if patch.len() - reader.position == 3 {
+        target.truncate(reader.number(3)?);
+    }
+    Ok(target)
+}
+
+fn apply_bps(patch: &[u8], rom: &[u8]) -> Result<Vec<u8>, String> {
+    if patch.len() < BPS_MAGIC.len() + BPS_FOOTER_SIZE {
+        return Err("patch ends early".to_string());
+    }
+    let actions_end = patch.len() - BPS_FOOTER_SIZE;
+    let mut footer = Reader::new(patch, actions_end);
+    let checksum = |reader: &mut Reader| -> Result<u32, String> {
+        Ok(reader.take(4)?.iter().rev().fold(0, |crc, &byte| crc << 8 | byte as u32))
+    };
+    let (source_crc, target_crc, patch_crc) =
+        (checksum(&mut footer)?, checksum(&mut footer)?, checksum(&mut footer)?);
+    if crc32(&patch[..patch.len() - 4]) != patch_crc {
+        return Err("patch is corrupt, its checksum doesn't match".to_string());
+    }
+    if crc32(rom) != source_crc {
+        return Err("patch is for a different ROM, the checksum doesn't match".to_string());
+    }
+
+    let mut reader = Reader::new(&patch[..actions_end], BPS_MAGIC.len());
+    let source_size = reader.varint()?;
+    let target_size = reader.varint()?;
+    let metadata_size = reader.varint()?;
+    reader.take(metadata_size)?;
+    if source_size != rom.len() {
+        return Err(format!("patch is for a {} byte ROM", source_size));
+    }
+
+    let mut target = Vec::new();
+    let mut source_offset = 0usize;
+    let mut target_offset = 0usize;
+    while reader.position < actions_end {
+        let action = reader.varint()?;
+        let length = (action >> 2) + 1;
+        if target.len() + length > target_size {
+            return Err("patch writes past the end of the ROM".to_string());
+        }
+        match action & 3 {
+            // source read, from the same offset in the ROM
+            0 => {
+                let start = target.len();
+                let bytes = rom.get(start..start + length).ok_or("patch reads past the ROM")?;
+                target.extend_from_slice(bytes);
+            },
+            // target read, from the patch
+            1 => target.extend_from_slice(reader.take(length)?),
+            // source and target copy, from anywhere relative to the last copy
+            kind => {
+                let data = reader.varint()?;
+                let delta = (data >> 1) as isize;
+                let delta = if data & 1 == 1 { -delta } else { delta };
+                let offset = if kind == 2 { &mut source_offset } else { &mut target_offset };
+                *offset = offset.checked_add_signed(delta).ok_or("patch copies from before the ROM")?;
+
+                for _ in 0..length {
+                    let byte = if kind == 2 { rom.get(*offset) } else { target.get(*offset) };
+                    let byte = *byte.ok_or("patch copies from past the ROM")?;
+                    target.push(byte);
+                    *offset += 1;
+                }
+            }
+        }
+    }
+
+    if target.len() != target_size {
+        return Err(format!("patch made {} bytes instead of {}", target.len(), target_size));
+    }
+    if crc32(&target) != target_crc {
+        return Err("patched ROM is corrupt, its checksum doesn't match".to_string());
+    }
+    Ok(target)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn varint(mut number: usize, bytes: &mut Vec<u8>) {
+        loop {
+            let byte = (number & 0x7F) as u8;
+            number >>= 7;
+            if number == 0 {
+                bytes.push(byte | 0x80);
+                return;
+            }
+            bytes.push(byte);
+            number -= 1;
+        }
+    }
+
+    fn bps(source: &[u8], target: &[u8], actions: &[u8]) -> Vec<u8> {
+        let mut patch = BPS_MAGIC.to_vec();
+        varint(source.len(), &mut patch);
+        varint(target.len(), &mut patch);
+        varint(0, &mut patch);
+        patch.extend_from_slice(actions);
+        patch.extend_from_slice(&crc32(source).to_le_bytes());
+        patch.extend_from_slice(&crc32(target).to_le_bytes());
+        let crc = crc32(&patch);
+        patch.extend_from_slice(&crc.to_le_bytes());
+        patch
+    }
+
+    #[test]
+    fn test_crc32() {
+        assert_eq!(crc32(b""), 0);
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+    }
+
+    #[test]
+    fn test_ips() {
+        let mut patch = b"PATCH".to_vec();
+        // two bytes at 1
+        patch.extend_from_slice(&[0, 0, 1, 0, 2, 0xAA, 0xBB]);
+        // three 0xCC at 5, past the end of the ROM
+        patch.extend_from_slice(&[0, 0, 5, 0, 0, 0, 3, 0xCC]);
+        patch.extend_from_slice(b"EOF");
+        assert_eq!(apply(&patch, &[1, 2, 3, 4]).unwrap(), vec![1, 0xAA, 0xBB, 4, 0, 0xCC, 0xCC, 0xCC]);
+
+        // truncated to 2 bytes
+        patch.extend_from_slice(&[0, 0, 2]);
+        assert_eq!(apply(&patch, &[1, 2, 3, 4]).unwrap(), vec![1, 0xAA]);
+
+        assert!(apply(b"PATCH\x00\x00\x01\x00\x05\xAA", &[1, 2, 3, 4]).is_err());
+        assert!(apply(b"nonsense", &[1, 2, 3, 4]).is_err());
+    }
+
+    #[test]
+    fn test_bps() {
+        let source = b"abcdef";
+        let target = b"abXYcdefab";
+        let mut actions = Vec::new();
+        // source read "ab"
+        varint((2 - 1) << 2, &mut actions);
+        // target read "XY"
+        varint((2 - 1) << 2 | 1, &mut actions);
+        actions.extend_from_slice(b"XY");
+        // source copy "cdef" from 2
+        varint((4 - 1) << 2 | 2, &mut actions);
+        varint(2 << 1, &mut actions);
+        // target copy "ab" from 0
+        varint((2 - 1) << 2 | 3, &mut actions);
+        varint(0, &mut actions);
+
+        let patch = bps(source, target, &actions);
+        assert_eq!(apply(&patch, source).unwrap(), target.to_vec());
+
+        // the wrong ROM
+        assert!(apply(&patch, b"abcdeg").is_err());
+        // a damaged patch
+        let mut damaged = patch.clone();
+        damaged[8] ^= 1;
+        assert!(apply(&damaged, source).is_err());
+        // a target checksum that doesn't match what the actions make
+        assert!(apply(&bps(source, b"abXYcdefaX", &actions), source).is_err());
+    }
+}
@@ -0,0 +1,320 @@
+/// Post-processing effects applied on the CPU after the display has been
+/// coloured, so they work without any GPU support
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Effects {
+    /// Darkened line between pixel rows
+    pub scanlines: bool,
+    /// Darkened lines between pixel rows and columns, like an LCD
+    pub grid: bool,
+    /// Lit pixels bleed light into their surroundings
+    pub glow: bool,
+    /// Picture bulges out like the glass of a CRT
+    pub curvature: bool
+}
+
+// presets cycled through at runtime
+const PRESETS: [&str; 4] = ["none", "scanlines", "grid", "crt"];
+
+impl Effects {
+    /// Parses a comma separated list of effects: scanlines, grid, glow and
+    /// curvature. `crt` is short for scanlines, glow and curvature and
+    /// `none` turns everything off.
+    pub fn parse(list: &str) -> Result<Self, String> {
+        let mut effects = Effects::default();
+        for name in list.split(',') {
+            match name.trim() {
+                "none" => {},
+                "scanlines" => effects.scanlines = true,
+                "grid" => effects.grid = true,
+                "glow" => effects.glow = true,
+                "curvature" => effects.curvature = true,
+                "crt" => {
+                    effects.scanlines = true;
+                    effects.glow = true;
+                    effects.curvature = true;
+                },
+                other => return Err(format!("unknown effect {}", other))
+            }
+        }
+        Ok(effects)
+    }
+
+    pub fn is_none(&self) -> bool {
+        *self == Effects::default()
+    }
+
+    /// The preset after this one, a custom combination moves to the first
+    pub fn next_preset(self) -> Self {
+        let current = PRESETS.iter()
+            .position(|preset| Effects::parse(preset).unwrap() == self);
+        let next = current.map_or(0, |index| (index + 1) % PRESETS.len());
+        Effects::parse(PRESETS[next]).unwrap()
+    }
+}
+
+/// How much each display pixel is blown up before the effects run
+const SCALE: usize = 4;
+const SCANLINE_DIM: f32 = 0.5;
+const GRID_DIM: f32 = 0.7;
+const GLOW_STRENGTH: f32 = 0.6;
+// how far the corners are pulled in by the curvature
+const CURVATURE: f32 = 0.08;
+
+/// Upscales RGBA frames and runs the enabled effects over them
+pub struct PostProcessor {
+    effects: Effects,
+    frame: Vec<u8>,
+    scratch: Vec<u8>
+}
+
+impl PostProcessor {
+    pub fn new(effects: Effects) -> Self {
+        PostProcessor {
+            effects,
+            frame: Vec::new(),
+            scratch: Vec::new()
+        }
+    }
+
+    pub fn effects(&self) -> Effects {
+        self.effects
+    }
+
+    pub fn set_effects(&mut self, effects: Effects) {
+        self.effects = effects;
+    }
+
+    /// Size of the processed frame for a frame of the given size, frames
+    /// are passed through untouched when no effect is enabled
+    pub fn output_size(&self, size: (usize, usize)) -> (usize, usize) {
+        if self.effects.is_none() {
+            size
+        } else {
+            (size.0 * SCALE, size.1 * SCALE)
+        }
+    }
+
+    /// Processes a frame of RGBA bytes, returning the result which is
+    /// `output_size` large
+    pub fn process<'a>(&'a mut self, rgba: &'a [u8], size: (usize, usize)) -> &'a [u8] {
+        if self.effects.is_none() {
+            return rgba;
+        }
+
+        let (width, height) = self.output_size(size);
+        self.frame.resize(width * height * 4, 0);
+        upscale(rgba, size, &mut self.frame);
+
+        if self.effects.glow {
+            glow(&mut self.frame, (width, height), &mut self.scratch);
+        }
+        if self.effects.scanlines || self.effects.grid {
+            dim_lines(&mut self.frame, (width, height), self.effects);
+        }
+        if self.effects.curvature {
+            self.scratch.resize(self.frame.len(), 0);
+            curve(&self.frame, (width, height), &mut self.scratch);
+            self.frame.copy_from_slice(&self.scratch);
+        }
+
+        &self.frame
+    }
+}
+
+// nearest neighbour upscale by SCALE
+fn upscale(rgba: &[u8], size: (usize, usize), out: &mut [u8]) {
+    let width = size.0 * SCALE;
+    for y in 0..size.1 * SCALE {
+        for x in 0..width {
+            let src = ((y / SCALE) * size.0 + x / SCALE) * 4;
+            let dst = (y * width + x) * 4;
+            out[dst..dst + 4].copy_from_slice(&rgba[src..src + 4]);
+        }
+    }
+}
+
+// adds a blurred copy of the frame on top of itself
+fn glow(frame: &mut [u8], size: (usize, usize), scratch: &mut Vec<u8>) {
+    scratch.clear();
+    scratch.extend_from_slice(frame);
+    let radius = SCALE / 2 + 1;
+    box_blur(scratch, size, radius, true);
+    box_blur(scratch, size, radius, false);
+
+    for (pixel, blurred) in frame.chunks_mut(4).zip(scratch.chunks(4)) {
+        for channel in 0..3 {
+            let value = pixel[channel] as f32 + blurred[channel] as f32 * GLOW_STRENGTH;
+            pixel[channel] = value.min(255.0) as u8;
+        }
+    }
+}
+
+// running average over 2 * radius + 1 pixels along rows or columns
+fn box_blur(frame: &mut [u8], size: (usize, usize), radius: usize, horizontal: bool) {
+    let (lines, length) = if horizontal { (size.1, size.0) } else { (size.0, size.1) };
+    let index = |line: usize, i: usize| {
+        if horizontal { (line * size.0 + i) * 4 } else { (i * size.0 + line) * 4 }
+    };
+    let window = (2 * radius + 1) as u32;
+    let mut line_copy = vec![0u8; length * 4];
+
+    for line in 0..lines {
+        for i in 0..length {
+            let src = index(line, i);
+            line_copy[i * 4..i * 4 + 4].copy_from_slice(&frame[src..src + 4]);
+        }
+
+        for channel in 0..3 {
+            // pixels past the edges count as black
+            let mut sum: u32 = (0..radius.min(length)).map(|i| line_copy[i * 4 + channel] as u32).sum();
+            for i in 0..length {
+                if i + radius < length {
+                    sum += line_copy[(i + radius) * 4 + channel] as u32;
+                }
+                if i > radius {
+                    sum -= line_copy[(i - radius - 1) * 4 + channel] as u32;
+                }
+                frame[index(line, i) + channel] = (sum / window) as u8;
+            }
+        }
+    }
+}
+
+// darkens the last row, and with the grid also the last column, of every
+// upscaled pixel
+fn dim_lines(frame: &mut [u8], size: (usize, usize), effects: Effects) {
+    for y in 0..size.1 {
+        for x in 0..size.0 {
+            let last_row = y % SCALE == SCALE - 1;
+            let last_column = x % SCALE == SCALE - 1;
+            let factor = if effects.scanlines && last_row {
+                SCANLINE_DIM
+            } else if effects.grid && (last_row || last_column) {
+                GRID_DIM
+            } else {
+                continue;
+            };
+
+            let i = (y * size.0 + x) * 4;
+            for channel in &mut frame[i..i + 3] {
+                *channel = (*channel as f32 * factor) as u8;
+            }
+        }
+    }
+}
+
+// barrel distortion, every output pixel samples the frame further out the
+// further it is from the centre. the corners end up black
+fn curve(frame: &[u8], size: (usize, usize), out: &mut [u8]) {
+    let (width, height) = (size.0 as f32, size.1 as f32);
+    for y in 0..size.1 {
+        for x in 0..size.0 {
+            let u = (x as f32 + 0.5) / width * 2.0 - 1.0;
+            let v = (y as f32 + 0.5) / height * 2.0 - 1.0;
+            let src_u = u * (1.0 + CURVATURE * v * v);
+            let src_v = v * (1.0 + CURVATURE * u * u);
+
+            let dst = (y * size.0 + x) * 4;
+            if src_u.abs() > 1.0 || src_v.abs() > 1.0 {
+                out[dst..dst + 4].copy_from_slice(&[0, 0, 0, 255]);
+                continue;
+            }
+            let src_x = (((src_u + 1.0) / 2.0 * width) as usize).min(size.0 - 1);
+            let src_y = (((src_v + 1.0) / 2.0 * height) as usize).min(size.1 - 1);
+            let src = (src_y * size.0 + src_x) * 4;
+            out[dst..dst + 4].copy_from_slice(&frame[src..src + 4]);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 2x1 frame with a white and a black pixel
+    const FRAME: [u8; 8] = [255, 255, 255, 255, 0, 0, 0, 255];
+
+    fn pixel(frame: &[u8], width: usize, x: usize, y: usize) -> &[u8] {
+        let i = (y * width + x) * 4;
+        &frame[i..i + 4]
+    }
+
+    #[test]
+    fn test_parse() {
+        let effects = Effects::parse("scanlines, glow").unwrap();
+        assert!(effects.scanlines && effects.glow);
+        assert!(!effects.grid && !effects.curvature);
+
+        let crt = Effects::parse("crt").unwrap();
+        assert!(crt.scanlines && crt.glow && crt.curvature);
+
+        assert!(Effects::parse("none").unwrap().is_none());
+        assert!(Effects::parse("vhs").is_err());
+    }
+
+    #[test]
+    fn test_next_preset() {
+        let none = Effects::default();
+        let scanlines = none.next_preset();
+        assert_eq!(scanlines, Effects::parse("scanlines").unwrap());
+        assert_eq!(scanlines.next_preset().next_preset().next_preset(), none);
+
+        // custom combinations start over
+        assert_eq!(Effects::parse("glow").unwrap().next_preset(), none);
+    }
+
+    #[test]
+    fn test_passthrough() {
+        let mut processor = PostProcessor::new(Effects::default());
+        assert_eq!(processor.output_size((2, 1)), (2, 1));
+        assert_eq!(processor.process(&FRAME, (2, 1)), &FRAME[..]);
+    }
+
+    #[test]
+    fn test_scanlines() {
+        let mut processor = PostProcessor::new(Effects::parse("scanlines").unwrap());
+        let (width, height) = processor.output_size((2, 1));
+        assert_eq!((width, height), (2 * SCALE, SCALE));
+
+        let frame = processor.process(&FRAME, (2, 1));
+        assert_eq!(pixel(frame, width, 0, 0), &[255, 255, 255, 255]);
+        assert_eq!(pixel(frame, width, SCALE - 1, 0), &[255, 255, 255, 255]);
+        assert_eq!(pixel(frame, width, 0, SCALE - 1), &[127, 127, 127, 255]);
+        assert_eq!(pixel(frame, width, SCALE, 0), &[0, 0, 0, 255]);
+    }
+
+    #[test]
+    fn test_grid() {
+        let mut processor = PostProcessor::new(Effects::parse("grid").unwrap());
+        let width = processor.output_size((2, 1)).0;
+
+        let frame = processor.process(&FRAME, (2, 1));
+        assert_eq!(pixel(frame, width, 0, 0), &[255, 255, 255, 255]);
+        assert_eq!(pixel(frame, width, SCALE - 1, 0), &[178, 178, 178, 255]);
+        assert_eq!(pixel(frame, width, 0, SCALE - 1), &[178, 178, 178, 255]);
+    }
+
+    #[test]
+    fn test_glow() {
+        let mut processor = PostProcessor::new(Effects::parse("glow").unwrap());
+        let width = processor.output_size((2, 1)).0;
+
+        let frame = processor.process(&FRAME, (2, 1));
+        // light spills over into the black pixel next to the white one
+        assert!(pixel(frame, width, SCALE, 1)[0] > 0);
+        assert_eq!(pixel(frame, width, 2 * SCALE - 1, 1), &[0, 0, 0, 255]);
+    }
+
+    #[test]
+    fn test_curvature() {
+        let mut processor = PostProcessor::new(Effects::parse("curvature").unwrap());
+        let white = [255; 16 * 8 * 4];
+        let (width, height) = processor.output_size((16, 8));
+
+        let frame = processor.process(&white, (16, 8));
+        // corners fall outside the picture, the centre stays
+        assert_eq!(pixel(frame, width, 0, 0), &[0, 0, 0, 255]);
+        assert_eq!(pixel(frame, width, width - 1, height - 1), &[0, 0, 0, 255]);
+        assert_eq!(pixel(frame, width, width / 2, height / 2), &[255, 255, 255, 255]);
+    }
+}
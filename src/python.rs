@@ -0,0 +1,257 @@
+//! Python module, built with `--features python`. The easiest way to
+//! build and install it is `maturin develop`, which builds the shared
+//! library itself, see `pyproject.toml`.
+//!
+//! ```python
+//! import chip8, numpy
+//! emulator = chip8.Chip8(seed=1)
+//! emulator.load_rom(open("pong.ch8", "rb").read())
+//! emulator.run_frame()
+//! pixels = numpy.asarray(emulator.framebuffer())  # 32 by 64 uint8
+//! ```
+
+use std::os::raw::{c_char, c_int, c_void};
+use std::ptr;
+
+use pyo3::exceptions::{PyBufferError, PyValueError};
+use pyo3::ffi;
+use pyo3::prelude::*;
+use pyo3::types::PyBytes;
+
+use cpu::{self, Chip8 as Cpu};
+
+/// A chip8 with its keypad and display
+#[pyclass(name = "Chip8")]
+struct Emulator {
+    cpu: Cpu,
+    seed: u64
+}
+
+#[pymethods]
+impl Emulator {
+    /// The seed drives the random numbers of Cxkk, the same seed and input
+    /// always give the same run
+    #[new]
+    #[pyo3(signature = (seed=0))]
+    fn new(seed: u64) -> Self {
+        Emulator {
+            cpu: Cpu::with_seed(seed),
+            seed
+        }
+    }
+
+    /// Resets the machine and loads a program
+    fn load_rom(&mut self, rom: &[u8]) -> PyResult<()> {
+        if rom.len() > cpu::MAX_ROM_SIZE {
+            return Err(PyValueError::new_err(format!(
+                "ROM is {} bytes, at most {} fit in memory", rom.len(), cpu::MAX_ROM_SIZE)));
+        }
+        let wait_mode = self.cpu.keypad.wait_mode();
+        self.cpu = Cpu::with_seed(self.seed);
+        self.cpu.keypad.set_wait_mode(wait_mode);
+        self.cpu.load_rom(rom);
+        Ok(())
+    }
+
+    /// Runs a single instruction. Timers only count down once a frame, in
+    /// run_frame.
+    fn step(&mut self) {
+        self.cpu.cycle();
+    }
+
+    /// Runs a number of 60 hz frames
+    #[pyo3(signature = (frames=1))]
+    fn run_frame(&mut self, frames: usize) {
+        for _ in 0..frames {
+            self.cpu.run_frame();
+        }
+    }
+
+    #[getter]
+    fn width(&self) -> usize {
+        self.cpu.display.width()
+    }
+
+    #[getter]
+    fn height(&self) -> usize {
+        self.cpu.display.height()
+    }
+
+    /// Copies the display, one byte per pixel, which numpy.asarray turns
+    /// into a height by width array
+    fn framebuffer(&self) -> Framebuffer {
+        let pixels = self.cpu.display.pixels().iter()
+            .flat_map(|row| row.iter().map(|&pixel| pixel as u8))
+            .collect();
+        let (width, height) = (self.width() as isize, self.height() as isize);
+        Framebuffer {
+            pixels,
+            shape: [height, width],
+            strides: [width, 1]
+        }
+    }
+
+    /// Presses or releases one of the keys 0 to 15
+    fn set_key(&mut self, key: usize, down: bool) -> PyResult<()> {
+        if key > 0xF {
+            return Err(PyValueError::new_err(format!("there is no key {}", key)));
+        }
+        if down && !self.cpu.keypad.is_pressed(key) {
+            self.cpu.keypad.press(key);
+        } else if !down && self.cpu.keypad.is_pressed(key) {
+            self.cpu.keypad.release(key);
+        }
+        Ok(())
+    }
+
+    /// Held keys as a bitmask, bit n being key n
+    #[getter]
+    fn keys(&self) -> u16 {
+        self.cpu.keypad.state()
+    }
+
+    #[setter]
+    fn set_keys(&mut self, keys: u16) {
+        self.cpu.keypad.set_state(keys);
+    }
+
+    /// Whether the beeper is sounding
+    #[getter]
+    fn sound_active(&mut self) -> bool {
+        self.cpu.should_beep()
+    }
+
+    /// A copy of the 4 KB of RAM
+    fn ram<'py>(&self, py: Python<'py>) -> Bound<'py, PyBytes> {
+        PyBytes::new(py, &self.cpu.ram)
+    }
+
+    /// Snapshots the machine as bytes
+    fn save_state<'py>(&self, py: Python<'py>) -> Bound<'py, PyBytes> {
+        PyBytes::new(py, &self.cpu.save_state())
+    }
+
+    /// Restores a snapshot from save_state
+    fn load_state(&mut self, state: &[u8]) -> PyResult<()> {
+        self.cpu.load_state(state).map_err(PyValueError::new_err)
+    }
+}
+
+/// Read only pixels exposed through the buffer protocol
+#[pyclass(frozen)]
+struct Framebuffer {
+    pixels: Vec<u8>,
+    shape: [isize; 2],
+    strides: [isize; 2]
+}
+
+#[pymethods]
+impl Framebuffer {
+    fn __len__(&self) -> usize {
+        self.pixels.len()
+    }
+
+    unsafe fn __getbuffer__(slf: Bound<'_, Self>, view: *mut ffi::Py_buffer,
+                            flags: c_int) -> PyResult<()> {
+        if view.is_null() {
+            return Err(PyBufferError::new_err("view is null"));
+        }
+        if flags & ffi::PyBUF_WRITABLE == ffi::PyBUF_WRITABLE {
+            return Err(PyBufferError::new_err("framebuffer is read only"));
+        }
+
+        // the object is frozen and lives as long as the view holds it
+        let framebuffer = slf.get();
+        let view = &mut *view;
+        view.buf = framebuffer.pixels.as_ptr() as *mut c_void;
+        view.len = framebuffer.pixels.len() as isize;
+        view.readonly = 1;
+        view.itemsize = 1;
+        view.format = if flags & ffi::PyBUF_FORMAT == ffi::PyBUF_FORMAT {
+            b"B\0".as_ptr() as *mut c_char
+        } else {
+            ptr::null_mut()
+        };
+        // without ND the consumer wants plain bytes
+        if flags & ffi::PyBUF_ND == ffi::PyBUF_ND {
+            view.ndim = 2;
+            view.shape = framebuffer.shape.as_ptr() as *mut isize;
+        } else {
+            view.ndim = 1;
+            view.shape = ptr::null_mut();
+        }
+        view.strides = if flags & ffi::PyBUF_STRIDES == ffi::PyBUF_STRIDES {
+            framebuffer.strides.as_ptr() as *mut isize
+        } else {
+            ptr::null_mut()
+        };
+        view.suboffsets = ptr::null_mut();
+        view.internal = ptr::null_mut();
+        view.obj = slf.into_any().into_ptr();
+        Ok(())
+    }
+
+    unsafe fn __releasebuffer__(&self, _view: *mut ffi::Py_buffer) {}
+}
+
+#[pymodule]
+fn chip8(module: &Bound<'_, PyModule>) -> PyResult<()> {
+    module.add_class::<Emulator>()?;
+    module.add_class::<Framebuffer>()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // draws the font sprite for 0 at the top left, then loops
+    const ROM: [u8; 4] = [0xD0, 0x05, 0x12, 0x02];
+
+    #[test]
+    fn test_load_rom() {
+        let mut emulator = Emulator::new(3);
+        assert!(emulator.load_rom(&ROM).is_ok());
+        assert_eq!(emulator.cpu.ram[0x200..0x204], ROM);
+        assert!(emulator.load_rom(&[0; cpu::MAX_ROM_SIZE + 1]).is_err());
+    }
+
+    #[test]
+    fn test_framebuffer() {
+        let mut emulator = Emulator::new(0);
+        emulator.load_rom(&ROM).unwrap();
+        emulator.run_frame(1);
+
+        let framebuffer = emulator.framebuffer();
+        assert_eq!(framebuffer.__len__(), 64 * 32);
+        assert_eq!(framebuffer.shape, [32, 64]);
+        assert_eq!(&framebuffer.pixels[..5], &[1, 1, 1, 1, 0]);
+        assert_eq!(framebuffer.pixels[64], 1);
+    }
+
+    #[test]
+    fn test_set_key() {
+        let mut emulator = Emulator::new(0);
+        emulator.set_key(0xA, true).unwrap();
+        assert_eq!(emulator.keys(), 1 << 0xA);
+        emulator.set_key(0xA, false).unwrap();
+        assert_eq!(emulator.keys(), 0);
+        assert!(emulator.set_key(0x10, true).is_err());
+    }
+
+    #[test]
+    fn test_save_and_load_state() {
+        pyo3::prepare_freethreaded_python();
+        Python::with_gil(|py| {
+            let mut emulator = Emulator::new(0);
+            emulator.load_rom(&ROM).unwrap();
+            let state = emulator.save_state(py);
+
+            emulator.run_frame(1);
+            emulator.load_state(state.as_bytes()).unwrap();
+            assert!(emulator.framebuffer().pixels.iter().all(|&pixel| pixel == 0));
+            assert_eq!(emulator.save_state(py).as_bytes(), state.as_bytes());
+            assert!(emulator.load_state(b"nonsense").is_err());
+        });
+    }
+}
@@ -0,0 +1,119 @@
+use sdl2::pixels::PixelFormatEnum;
+use sdl2::rect::Rect;
+use sdl2::render::{Canvas, Texture, TextureCreator};
+use sdl2::video::{Window, WindowContext};
+
+use display::Display;
+use filter::Persistence;
+use palette::Palette;
+use postfx::{Effects, PostProcessor};
+
+// SDL's packed formats depend on endianness, these are the ones laid out
+// as R, G, B, A bytes in memory
+#[cfg(target_endian = "little")]
+const RGBA: PixelFormatEnum = PixelFormatEnum::ABGR8888;
+#[cfg(target_endian = "big")]
+const RGBA: PixelFormatEnum = PixelFormatEnum::RGBA8888;
+
+/// Draws the display through a streaming texture and lets SDL scale it up,
+/// instead of filling a rectangle per pixel. The texture is as large as the
+/// display, or as the upscaled frame when post-processing is enabled.
+pub struct Renderer<'a> {
+    texture_creator: &'a TextureCreator<WindowContext>,
+    texture: Texture<'a>,
+    texture_size: (usize, usize),
+    palette: Palette,
+    persistence: Persistence,
+    post_processor: PostProcessor,
+    rgba: Vec<u8>,
+    // forces the next update to redraw after settings changed
+    dirty: bool
+}
+
+impl<'a> Renderer<'a> {
+    pub fn new(texture_creator: &'a TextureCreator<WindowContext>, display: &Display,
+               palette: Palette, persistence: Persistence, effects: Effects) -> Self {
+        let post_processor = PostProcessor::new(effects);
+        let texture_size = post_processor.output_size((display.width(), display.height()));
+
+        Renderer {
+            texture_creator,
+            texture: Renderer::create_texture(texture_creator, texture_size),
+            texture_size,
+            palette,
+            persistence,
+            post_processor,
+            rgba: Vec::new(),
+            dirty: true
+        }
+    }
+
+    pub fn effects(&self) -> Effects {
+        self.post_processor.effects()
+    }
+
+    pub fn set_effects(&mut self, effects: Effects) {
+        self.post_processor.set_effects(effects);
+        self.dirty = true;
+    }
+
+    fn create_texture(texture_creator: &'a TextureCreator<WindowContext>,
+                      size: (usize, usize)) -> Texture<'a> {
+        texture_creator.create_texture_streaming(RGBA, size.0 as u32, size.1 as u32).unwrap()
+    }
+
+    /// Uploads the display's pixels to the texture, only if they changed
+    /// since the last update or are still fading. Has to be called once per
+    /// frame for the persistence filter to keep time. The texture follows
+    /// the display when it switches resolution.
+    pub fn update(&mut self, display: &mut Display) {
+        let size = (display.width(), display.height());
+        let texture_size = self.post_processor.output_size(size);
+        if texture_size != self.texture_size {
+            self.texture = Renderer::create_texture(self.texture_creator, texture_size);
+            self.texture_size = texture_size;
+            self.dirty = true;
+        }
+
+        let changed = display.take_changed() || self.dirty;
+        if !changed && self.persistence.is_settled() {
+            return;
+        }
+        self.dirty = false;
+
+        self.rgba.resize(size.0 * size.1 * 4, 0);
+        self.persistence.apply(display);
+        to_rgba(self.persistence.levels(), &self.palette, &mut self.rgba);
+
+        let frame = self.post_processor.process(&self.rgba, size);
+        self.texture.update(None, frame, texture_size.0 * 4).unwrap();
+    }
+
+    /// Copies the texture into an area of the canvas
+    pub fn draw(&self, canvas: &mut Canvas<Window>, dst: Rect) {
+        canvas.copy(&self.texture, None, dst).unwrap();
+    }
+}
+
+/// Converts pixel brightness levels into RGBA bytes
+pub fn to_rgba(levels: &[f32], palette: &Palette, rgba: &mut [u8]) {
+    for (&level, out) in levels.iter().zip(rgba.chunks_mut(4)) {
+        out.copy_from_slice(&palette.blend(level));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_to_rgba() {
+        let palette = Palette::theme("lcd").unwrap();
+        let mut rgba = vec![0; 3 * 4];
+        to_rgba(&[0.0, 1.0, 0.0], &palette, &mut rgba);
+
+        assert_eq!(rgba[0..4], palette.rgba(0));
+        assert_eq!(rgba[4..8], palette.rgba(1));
+        assert_eq!(rgba[8..12], palette.rgba(0));
+    }
+}
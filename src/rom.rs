@@ -1,28 +1,433 @@
+use std::error::Error;
+use std::fmt;
 use std::fs::File;
-use std::path::Path;
-use std::io::Read;
+use std::io::{self, BufRead, Cursor, Read, Write};
+use std::path::{Path, PathBuf};
 
-pub fn load_rom_file(path: &str, buf: &mut [u8]) {
-    let path = Path::new(path);
+use zip::ZipArchive;
 
-    let mut file = match File::open(&path) {
-        Err(why) => panic!("Couldn't open ROM"),
-        Ok(file) => file
+use cpu;
+use patch;
+
+/// Path that reads the ROM from stdin
+pub const STDIN: &str = "-";
+const ZIP_MAGIC: &[u8] = b"PK\x03\x04";
+// extensions of ROMs looked for in archives
+const ROM_EXTENSIONS: [&str; 3] = ["ch8", "sc8", "xo8"];
+// extensions of files always read as hex dumps
+const HEX_EXTENSIONS: [&str; 2] = ["hex", "txt"];
+
+/// The machine a ROM was written for, going by its extension. Each loads
+/// programs at 0x200, but the memory above that differs.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Platform {
+    Chip8,
+    SuperChip,
+    XoChip
+}
+
+impl Platform {
+    pub fn from_path(path: &Path) -> Self {
+        let extension = path.extension().map(|extension| extension.to_string_lossy().to_lowercase());
+        match extension.as_deref() {
+            Some("sc8") => Platform::SuperChip,
+            Some("xo8") => Platform::XoChip,
+            _ => Platform::Chip8
+        }
+    }
+
+    /// Largest ROM the platform has room for
+    pub fn max_size(&self) -> usize {
+        match *self {
+            Platform::Chip8 | Platform::SuperChip => 0x1000 - 0x200,
+            Platform::XoChip => 0x10000 - 0x200
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match *self {
+            Platform::Chip8 => "CHIP-8",
+            Platform::SuperChip => "SUPER-CHIP",
+            Platform::XoChip => "XO-CHIP"
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum RomError {
+    Io { path: PathBuf, error: io::Error },
+    Patch { path: PathBuf, why: String },
+    /// A zip archive or hex text that couldn't be read
+    Format { path: PathBuf, why: String },
+    Empty,
+    /// Bigger than the platform allows
+    TooLarge { size: usize, platform: Platform },
+    /// Fits the platform, but not the memory this emulator has
+    Unsupported { size: usize, platform: Platform }
+}
+
+impl fmt::Display for RomError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            RomError::Io { ref path, ref error } => {
+                write!(f, "couldn't read {}: {}", path.display(), error)
+            },
+            RomError::Patch { ref path, ref why } => {
+                write!(f, "couldn't apply {}: {}", path.display(), why)
+            },
+            RomError::Format { ref path, ref why } => write!(f, "{}: {}", path.display(), why),
+            RomError::Empty => write!(f, "the ROM is empty"),
+            RomError::TooLarge { size, platform } => {
+                write!(f, "the ROM is {} bytes, {} ROMs are at most {}",
+                       size, platform.name(), platform.max_size())
+            },
+            RomError::Unsupported { size, platform } => {
+                write!(f, "the ROM is {} bytes, only {} fit in memory without {} support",
+                       size, cpu::MAX_ROM_SIZE, platform.name())
+            }
+        }
+    }
+}
+
+impl Error for RomError {}
+
+/// Loads a whole ROM, patched with `patch_path` if given or else with an
+/// IPS or BPS patch named like the ROM next to it, e.g. `pong.ips` for
+/// `pong.ch8`. ROMs too large for their platform or for memory are
+/// rejected.
+///
+/// Besides plain ROMs, `path` can be `-` for stdin, a zip archive holding
+/// a ROM, or a hex dump as text. Dumps are parsed when `hex` is set or the
+/// file ends in `.hex` or `.txt`, and otherwise only when the file doesn't
+/// pass as a binary ROM. Returns the ROM and the name of the file it came
+/// from, which is the one inside for archives and `stdin` for stdin.
+pub fn load_rom_file(path: &str, patch_path: Option<&str>, hex: bool) -> Result<(Vec<u8>, PathBuf), RomError> {
+    let (mut rom, name) = read_rom(path, hex)?;
+
+    let patch_path = patch_path.map(PathBuf::from).or_else(|| {
+        if path == STDIN { None } else { find_patch(Path::new(path)) }
+    });
+    if let Some(patch_path) = patch_path {
+        let patch = read_file(&patch_path)?;
+        rom = patch::apply(&patch, &rom)
+            .map_err(|why| RomError::Patch { path: patch_path.clone(), why })?;
+        println!("applied patch {}", patch_path.display());
+    }
+
+    validate(&rom, Platform::from_path(&name))?;
+    Ok((rom, name))
+}
+
+// the ROM and the name its platform is told from, which for archives is
+// the name of the ROM inside
+fn read_rom(path: &str, hex: bool) -> Result<(Vec<u8>, PathBuf), RomError> {
+    let (bytes, name) = if path == STDIN {
+        let mut bytes = Vec::new();
+        io::stdin().read_to_end(&mut bytes)
+            .map_err(|error| RomError::Io { path: PathBuf::from("stdin"), error })?;
+        (bytes, PathBuf::from("stdin"))
+    } else {
+        (read_file(Path::new(path))?, PathBuf::from(path))
     };
 
-    file.read(buf);
+    if bytes.starts_with(ZIP_MAGIC) {
+        // stdin is taken when the archive came from it
+        return read_zip(bytes, &name, path != STDIN);
+    }
+    if hex || has_extension(&name, &HEX_EXTENSIONS) {
+        let text = String::from_utf8_lossy(&bytes);
+        let rom = parse_hex(&text).map_err(|why| RomError::Format { path: name.clone(), why })?;
+        return Ok((rom, name));
+    }
+    // a dump too big to be a ROM, say, but stdin is only parsed when asked
+    if path != STDIN && validate(&bytes, Platform::from_path(&name)).is_err() && is_text(&bytes) {
+        if let Ok(rom) = parse_hex(&String::from_utf8_lossy(&bytes)) {
+            return Ok((rom, name));
+        }
+    }
+    Ok((bytes, name))
+}
+
+fn read_zip(bytes: Vec<u8>, path: &Path, prompt: bool) -> Result<(Vec<u8>, PathBuf), RomError> {
+    let error = |why: String| RomError::Format { path: path.to_path_buf(), why };
+    let mut archive = ZipArchive::new(Cursor::new(bytes)).map_err(|why| error(why.to_string()))?;
+
+    let mut names: Vec<String> = archive.file_names()
+        .filter(|name| has_extension(Path::new(name), &ROM_EXTENSIONS))
+        .map(String::from)
+        .collect();
+    names.sort();
+    let name = match names.len() {
+        0 => return Err(error("no .ch8, .sc8 or .xo8 file in the archive".to_string())),
+        1 => names.remove(0),
+        _ if prompt => choose(&names).map_err(|why| RomError::Io { path: PathBuf::from("stdin"), error: why })?,
+        _ => return Err(error(format!("the archive holds several ROMs: {}", names.join(", "))))
+    };
+
+    // the size in the archive can't be trusted, so reading stops one byte
+    // past the largest ROM for validate to reject
+    let platform = Platform::from_path(Path::new(&name));
+    let max_size = platform.max_size() as u64;
+    let entry = archive.by_name(&name).map_err(|why| error(why.to_string()))?;
+    if entry.size() > max_size {
+        return Err(RomError::TooLarge { size: entry.size() as usize, platform });
+    }
+    let mut rom = Vec::new();
+    entry.take(max_size + 1)
+        .read_to_end(&mut rom)
+        .map_err(|why| error(why.to_string()))?;
+    Ok((rom, PathBuf::from(name)))
+}
+
+// asks which of an archive's ROMs to run
+fn choose(names: &[String]) -> io::Result<String> {
+    for (number, name) in names.iter().enumerate() {
+        println!("{}) {}", number + 1, name);
+    }
+    let stdin = io::stdin();
+    let mut lines = stdin.lock().lines();
+    loop {
+        print!("which ROM? ");
+        io::stdout().flush()?;
+        let line = lines.next()
+            .unwrap_or_else(|| Err(io::Error::new(io::ErrorKind::UnexpectedEof, "no ROM chosen")))?;
+        match line.trim().parse::<usize>() {
+            Ok(number) if number >= 1 && number <= names.len() => {
+                return Ok(names[number - 1].clone());
+            },
+            _ => println!("pick a number from 1 to {}", names.len())
+        }
+    }
+}
+
+fn has_extension(path: &Path, extensions: &[&str]) -> bool {
+    path.extension()
+        .map(|extension| extension.to_string_lossy().to_lowercase())
+        .is_some_and(|extension| extensions.contains(&extension.as_str()))
+}
+
+// binary ROMs are all but certain to hold bytes that aren't printable
+fn is_text(bytes: &[u8]) -> bool {
+    !bytes.is_empty() && bytes.iter().all(|&byte| {
+        byte.is_ascii_graphic() || byte.is_ascii_whitespace()
+    })
+}
+
+/// Parses a hex dump the way they're shared on forums, with bytes written
+/// like `00 E0`, `00E0` or `0x00, 0xE0`, or as printed by `xxd` and
+/// `hexdump -C`. Anything after `#`, `;` or `//` is skipped. When the first
+/// line starts with an offset, either ending in `:` or longer than the word
+/// after it, every line does and they're skipped too, along with the text
+/// column after a `|` or, behind a `:` offset, after two spaces.
+pub fn parse_hex(text: &str) -> Result<Vec<u8>, String> {
+    let mut rom = Vec::new();
+    let mut offsets = None;
+    for (number, line) in text.lines().enumerate() {
+        let line = ["#", ";", "//", "|"].iter()
+            .fold(line, |line, cut| line.split(cut).next().unwrap())
+            .trim_start();
+        let mut words = line.split_whitespace();
+        let first = match words.next() {
+            Some(first) => first,
+            None => continue
+        };
+        let offsets = *offsets.get_or_insert_with(|| {
+            first.ends_with(':') || words.next().is_some_and(|second| first.len() > second.len())
+        });
+
+        let line = if offsets {
+            if first == "*" {
+                return Err(format!("line {}: repeated lines left out, dump with hexdump -v", number + 1));
+            }
+            let rest = line[first.len()..].trim_start();
+            if first.ends_with(':') { rest.split("  ").next().unwrap() } else { rest }
+        } else {
+            line
+        };
+
+        for word in line.split(|c: char| c.is_whitespace() || c == ',').filter(|word| !word.is_empty()) {
+            let digits = word.strip_prefix("0x").or_else(|| word.strip_prefix("0X"))
+                .or_else(|| word.strip_prefix('$'))
+                .unwrap_or(word);
+            if digits.is_empty() || digits.len() % 2 != 0 || !digits.chars().all(|c| c.is_ascii_hexdigit()) {
+                return Err(format!("line {}: {} isn't hex bytes", number + 1, word));
+            }
+            for pair in (0..digits.len()).step_by(2) {
+                rom.push(u8::from_str_radix(&digits[pair..pair + 2], 16).unwrap());
+            }
+        }
+    }
+    Ok(rom)
+}
+
+pub fn validate(rom: &[u8], platform: Platform) -> Result<(), RomError> {
+    let size = rom.len();
+    if size == 0 {
+        Err(RomError::Empty)
+    } else if size > platform.max_size() {
+        Err(RomError::TooLarge { size, platform })
+    } else if size > cpu::MAX_ROM_SIZE {
+        Err(RomError::Unsupported { size, platform })
+    } else {
+        Ok(())
+    }
+}
+
+fn read_file(path: &Path) -> Result<Vec<u8>, RomError> {
+    let mut bytes = Vec::new();
+    File::open(path)
+        .and_then(|mut file| file.read_to_end(&mut bytes))
+        .map_err(|error| RomError::Io { path: path.to_path_buf(), error })?;
+    Ok(bytes)
+}
+
+fn find_patch(rom_path: &Path) -> Option<PathBuf> {
+    ["ips", "bps"].iter()
+        .map(|extension| rom_path.with_extension(extension))
+        .find(|path| path.exists())
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::env;
+    use std::fs;
 
     #[test]
     fn test_load_rom_file() {
-        let mut rom = [0; 1000];
-        load_rom_file("assets/maze.ch8", &mut rom);
+        let (rom, name) = load_rom_file("assets/maze.ch8", None, false).unwrap();
+        assert_eq!(name, Path::new("assets/maze.ch8"));
 
+        assert_eq!(rom.len(), 191);
         assert_eq!(rom[0], 0x30);
         assert_eq!(rom[0x10], 0x32);
+
+        match load_rom_file("assets/missing.ch8", None, false) {
+            Err(RomError::Io { path, .. }) => assert_eq!(path, Path::new("assets/missing.ch8")),
+            result => panic!("expected an io error, got {:?}", result)
+        }
+    }
+
+    #[test]
+    fn test_load_patched() {
+        let dir = env::temp_dir().join("chip8-test-patch");
+        fs::create_dir_all(&dir).unwrap();
+        let rom_path = dir.join("game.ch8");
+        fs::write(&rom_path, [1, 2, 3]).unwrap();
+        let rom_path = rom_path.to_str().unwrap();
+
+        // found next to the ROM
+        fs::write(dir.join("game.ips"), b"PATCH\x00\x00\x01\x00\x01\xAAEOF").unwrap();
+        assert_eq!(load_rom_file(rom_path, None, false).unwrap().0, vec![1, 0xAA, 3]);
+
+        // given on the command line
+        let other = dir.join("other.ips");
+        fs::write(&other, b"PATCH\x00\x00\x02\x00\x01\xBBEOF").unwrap();
+        assert_eq!(load_rom_file(rom_path, other.to_str(), false).unwrap().0, vec![1, 2, 0xBB]);
+
+        fs::write(&other, b"garbage").unwrap();
+        assert!(load_rom_file(rom_path, other.to_str(), false).is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_validate() {
+        assert_eq!(Platform::from_path(Path::new("pong.ch8")), Platform::Chip8);
+        assert_eq!(Platform::from_path(Path::new("ant.SC8")), Platform::SuperChip);
+        assert_eq!(Platform::from_path(Path::new("dir/game.xo8")), Platform::XoChip);
+
+        assert!(validate(&[0; 3584], Platform::Chip8).is_ok());
+        assert!(matches!(validate(&[], Platform::Chip8), Err(RomError::Empty)));
+        assert!(matches!(validate(&[0; 3585], Platform::Chip8),
+                         Err(RomError::TooLarge { size: 3585, .. })));
+        assert!(matches!(validate(&[0; 3585], Platform::XoChip),
+                         Err(RomError::Unsupported { size: 3585, .. })));
+        assert!(matches!(validate(&[0; 0x10000], Platform::XoChip),
+                         Err(RomError::TooLarge { .. })));
+    }
+
+    #[test]
+    fn test_parse_hex() {
+        assert_eq!(parse_hex("00E0 a22a\n600C").unwrap(), vec![0x00, 0xE0, 0xA2, 0x2A, 0x60, 0x0C]);
+        assert_eq!(parse_hex("0x00, 0xE0,\n$12 // jump").unwrap(), vec![0x00, 0xE0, 0x12]);
+        assert_eq!(parse_hex("0200: 00 E0  # clear\n0202: 12 02").unwrap(),
+                   vec![0x00, 0xE0, 0x12, 0x02]);
+        assert_eq!(parse_hex("00000000  a2 1e c2 01 32 01").unwrap(),
+                   vec![0xA2, 0x1E, 0xC2, 0x01, 0x32, 0x01]);
+        let hexdump = "\
+00000000  a2 1e c2 01 32 01 a2 1a  d0 14 70 04 30 40 12 00  |....2.....p.0@..|
+00000010  60 00 71 04 31 20 12 00  |`.q.1 ..|
+00000018
+";
+        assert_eq!(parse_hex(hexdump).unwrap().len(), 24);
+        assert_eq!(parse_hex(hexdump).unwrap()[16..], [0x60, 0x00, 0x71, 0x04, 0x31, 0x20, 0x12, 0x00]);
+        assert!(parse_hex("00000000  00 00\n*\n00000020  12 00").is_err());
+        assert!(parse_hex("00E").is_err());
+        assert!(parse_hex("CLS").is_err());
+    }
+
+    #[test]
+    fn test_load_archive_and_hex() {
+        use zip::write::{SimpleFileOptions, ZipWriter};
+
+        let dir = env::temp_dir().join("chip8-test-archive");
+        fs::create_dir_all(&dir).unwrap();
+        let zip = |files: &[(&str, &[u8])]| {
+            let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+            for &(name, bytes) in files {
+                let options = SimpleFileOptions::default()
+                    .compression_method(zip::CompressionMethod::Stored);
+                writer.start_file(name, options).unwrap();
+                writer.write_all(bytes).unwrap();
+            }
+            writer.finish().unwrap().into_inner()
+        };
+
+        // the one ROM in the archive, whose name gives the platform
+        let path = dir.join("games.zip");
+        fs::write(&path, zip(&[("README.txt", b"hi"), ("games/ant.SC8", &[1, 2])])).unwrap();
+        let path = path.to_str().unwrap();
+        assert_eq!(load_rom_file(path, None, false).unwrap(), (vec![1, 2], PathBuf::from("games/ant.SC8")));
+
+        let path = dir.join("none.zip");
+        fs::write(&path, zip(&[("README.txt", b"hi")])).unwrap();
+        assert!(load_rom_file(path.to_str().unwrap(), None, false).is_err());
+
+        let path = dir.join("several.zip");
+        fs::write(&path, zip(&[("a.ch8", &[1]), ("b.ch8", &[2])])).unwrap();
+        match read_zip(fs::read(&path).unwrap(), &path, false) {
+            Err(RomError::Format { why, .. }) => assert!(why.ends_with("a.ch8, b.ch8")),
+            result => panic!("expected a format error, got {:?}", result)
+        }
+
+        // stored entries read as far as the platform allows
+        let path = dir.join("huge.zip");
+        fs::write(&path, zip(&[("huge.ch8", &[0; 0x1000])])).unwrap();
+        match read_zip(fs::read(&path).unwrap(), &path, false) {
+            Err(RomError::TooLarge { size: 0x1000, .. }) => {},
+            result => panic!("expected a too large error, got {:?}", result)
+        }
+
+        // the maze asset is an xxd dump of the ROM
+        let dump = fs::read("assets/maze.ch8").unwrap();
+        let maze = parse_hex(&String::from_utf8(dump.clone()).unwrap()).unwrap();
+        assert_eq!(maze.len(), 35);
+        let opcodes: Vec<u16> = maze[..8].chunks(2).map(|pair| (pair[0] as u16) << 8 | pair[1] as u16).collect();
+        assert_eq!(opcodes, [0xA21E, 0xC201, 0x3201, 0xA21A]);
+        assert_eq!(maze[32..], [0x80, 0x10, 0x0A]);
+
+        let path = dir.join("maze.hex");
+        fs::write(&path, &dump).unwrap();
+        assert_eq!(load_rom_file(path.to_str().unwrap(), None, false).unwrap().0, maze);
+        let path = dir.join("maze.ch8");
+        fs::write(&path, &dump).unwrap();
+        assert_eq!(load_rom_file(path.to_str().unwrap(), None, true).unwrap().0, maze);
+
+        // text in a .ch8 file is taken as it is, unless it's too big for a ROM
+        assert_eq!(load_rom_file(path.to_str().unwrap(), None, false).unwrap().0, dump);
+        let big = "00E0 ".repeat(1000);
+        fs::write(&path, &big).unwrap();
+        assert_eq!(load_rom_file(path.to_str().unwrap(), None, false).unwrap().0.len(), 2000);
+        fs::remove_dir_all(&dir).unwrap();
     }
 }
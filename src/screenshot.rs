@@ -0,0 +1,87 @@
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+use png;
+
+use display::Display;
+use palette::Palette;
+
+/// Colours the display with a palette, every chip8 pixel becoming a
+/// `scale` by `scale` square. Returns the RGBA bytes with their width and
+/// height.
+pub fn render(display: &Display, palette: &Palette, scale: usize) -> (Vec<u8>, usize, usize) {
+    let width = display.width() * scale;
+    let height = display.height() * scale;
+    let mut rgba = Vec::with_capacity(width * height * 4);
+
+    for row in display.pixels().iter() {
+        for _ in 0..scale {
+            for &pixel in row.iter() {
+                let color = palette.rgba(pixel as usize);
+                for _ in 0..scale {
+                    rgba.extend_from_slice(&color);
+                }
+            }
+        }
+    }
+
+    (rgba, width, height)
+}
+
+/// Encodes RGBA bytes as a PNG
+pub fn write_png<W: Write>(writer: W, rgba: &[u8], width: usize, height: usize) -> Result<(), String> {
+    let mut encoder = png::Encoder::new(writer, width as u32, height as u32);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+
+    encoder.write_header()
+        .and_then(|mut writer| writer.write_image_data(rgba))
+        .map_err(|why| why.to_string())
+}
+
+/// Saves the display as a PNG file
+pub fn save_png(path: &Path, display: &Display, palette: &Palette, scale: usize) -> Result<(), String> {
+    let (rgba, width, height) = render(display, palette, scale);
+    let file = File::create(path)
+        .map_err(|why| format!("couldn't create {}: {}", path.display(), why))?;
+
+    write_png(BufWriter::new(file), &rgba, width, height)
+        .map_err(|why| format!("couldn't write {}: {}", path.display(), why))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render() {
+        let mut display = Display::new();
+        display.draw(1, 0, 1, &[0x80]);
+        let palette = Palette::default();
+
+        let (rgba, width, height) = render(&display, &palette, 2);
+        assert_eq!((width, height), (128, 64));
+        assert_eq!(rgba.len(), 128 * 64 * 4);
+
+        // pixel (1, 0) covers (2..4, 0..2)
+        assert_eq!(rgba[0..4], palette.rgba(0));
+        assert_eq!(rgba[8..12], palette.rgba(1));
+        assert_eq!(rgba[(128 + 3) * 4..(128 + 4) * 4], palette.rgba(1));
+        assert_eq!(rgba[(2 * 128 + 2) * 4..(2 * 128 + 3) * 4], palette.rgba(0));
+    }
+
+    #[test]
+    fn test_write_png() {
+        let (rgba, width, height) = render(&Display::new(), &Palette::default(), 1);
+        let mut bytes = Vec::new();
+        write_png(&mut bytes, &rgba, width, height).unwrap();
+
+        assert_eq!(&bytes[1..4], b"PNG");
+
+        let decoder = png::Decoder::new(&bytes[..]);
+        let reader = decoder.read_info().unwrap();
+        assert_eq!(reader.info().width, 64);
+        assert_eq!(reader.info().height, 32);
+    }
+}
@@ -0,0 +1,379 @@
+//! Rhai scripts that run alongside the emulator, for bots, overlays and
+//! test scripts. The top level of a script runs once when it is loaded,
+//! then these functions are called if the script defines them:
+//!
+//! ```text
+//! fn on_key(emu, key, pressed) {}      // before a frame, for each key that changed
+//! fn on_instruction(emu, pc, opcode) {} // after a frame, for each instruction it ran
+//! fn on_write(emu, address, value) {}  // after a frame, for each write to ram
+//! fn on_frame(emu) {}                  // after each frame
+//! ```
+//!
+//! The CPU runs a whole frame at a time, so `on_instruction` and `on_write`
+//! are called in the order things happened but see the machine as it is at
+//! the end of the frame.
+//!
+//! `emu` reads and changes the emulator: `emu.pc`, `emu.i`, `emu.frame`,
+//! `emu.v(x)`, `emu.set_v(x, value)`, `emu.peek(address)`,
+//! `emu.poke(address, value)`, `emu.pixel(x, y)`, `emu.width`,
+//! `emu.height`, `emu.draw(x, y, [rows])`, `emu.pressed(key)`,
+//! `emu.press(key)`, `emu.release(key)` and `emu.quit()`. Functions can't
+//! see the script's variables, so `this` is a map kept between calls for
+//! anything a script needs to remember.
+
+use std::cell::Cell;
+use std::fs;
+use std::ptr;
+use std::rc::Rc;
+
+use rhai::{Array, CallFnOptions, Dynamic, Engine, EvalAltResult, FuncArgs, Map, Scope, AST};
+
+use cpu::{Chip8, Trace};
+
+/// The emulator as scripts see it. It points at the real CPU while hooks
+/// run, and at nothing in between.
+#[derive(Clone)]
+struct Emu {
+    cpu: Rc<Cell<*mut Chip8>>,
+    frame: Rc<Cell<i64>>,
+    quit: Rc<Cell<bool>>
+}
+
+type ScriptResult<T> = Result<T, Box<EvalAltResult>>;
+
+impl Emu {
+    fn register(engine: &mut Engine) {
+        engine.register_type_with_name::<Emu>("Emu")
+            .register_get("pc", |emu: &mut Emu| emu.with(|cpu| Ok(cpu.pc() as i64)))
+            .register_get("i", |emu: &mut Emu| emu.with(|cpu| Ok(cpu.i() as i64)))
+            .register_get("frame", |emu: &mut Emu| emu.frame.get())
+            .register_get("width", |emu: &mut Emu| emu.with(|cpu| Ok(cpu.display.width() as i64)))
+            .register_get("height", |emu: &mut Emu| emu.with(|cpu| Ok(cpu.display.height() as i64)))
+            .register_fn("v", |emu: &mut Emu, x: i64| -> ScriptResult<i64> {
+                emu.with(|cpu| Ok(cpu.v(register(x)?) as i64))
+            })
+            .register_fn("set_v", |emu: &mut Emu, x: i64, value: i64| -> ScriptResult<()> {
+                emu.with(|cpu| {
+                    cpu.set_v(register(x)?, byte(value)?);
+                    Ok(())
+                })
+            })
+            .register_fn("peek", |emu: &mut Emu, address: i64| -> ScriptResult<i64> {
+                emu.with(|cpu| Ok(cpu.ram[ram_address(cpu, address)?] as i64))
+            })
+            .register_fn("poke", |emu: &mut Emu, address: i64, value: i64| -> ScriptResult<()> {
+                emu.with(|cpu| {
+                    let address = ram_address(cpu, address)?;
+                    cpu.ram[address] = byte(value)?;
+                    Ok(())
+                })
+            })
+            .register_fn("pixel", |emu: &mut Emu, x: i64, y: i64| -> ScriptResult<bool> {
+                emu.with(|cpu| {
+                    let display = &cpu.display;
+                    if x < 0 || y < 0 || x as usize >= display.width() || y as usize >= display.height() {
+                        return Err(format!("pixel {}, {} is off the display", x, y).into());
+                    }
+                    Ok(display.pixels()[y as usize][x as usize])
+                })
+            })
+            .register_fn("draw", |emu: &mut Emu, x: i64, y: i64, rows: Array| -> ScriptResult<bool> {
+                let sprite = rows.into_iter()
+                    .map(|row| row.as_int().map_err(|_| "sprite rows must be numbers".into()).and_then(byte))
+                    .collect::<ScriptResult<Vec<u8>>>()?;
+                if x < 0 || y < 0 {
+                    return Err(format!("can't draw at {}, {}", x, y).into());
+                }
+                emu.with(|cpu| Ok(cpu.display.draw(x as usize, y as usize, sprite.len(), &sprite)))
+            })
+            .register_fn("pressed", |emu: &mut Emu, key: i64| -> ScriptResult<bool> {
+                emu.with(|cpu| Ok(cpu.keypad.is_pressed(keypad_key(key)?)))
+            })
+            .register_fn("press", |emu: &mut Emu, key: i64| -> ScriptResult<()> {
+                emu.with(|cpu| {
+                    cpu.keypad.press(keypad_key(key)?);
+                    Ok(())
+                })
+            })
+            .register_fn("release", |emu: &mut Emu, key: i64| -> ScriptResult<()> {
+                emu.with(|cpu| {
+                    cpu.keypad.release(keypad_key(key)?);
+                    Ok(())
+                })
+            })
+            .register_fn("quit", |emu: &mut Emu| emu.quit.set(true));
+    }
+
+    // points the emu at `cpu`, which stays borrowed until the guard is
+    // dropped
+    fn attach<'a>(&'a self, cpu: &'a mut Chip8) -> Attached<'a> {
+        self.cpu.set(cpu);
+        Attached { cpu: &self.cpu }
+    }
+
+    fn with<T, F: FnOnce(&mut Chip8) -> ScriptResult<T>>(&self, f: F) -> ScriptResult<T> {
+        let cpu = self.cpu.get();
+        if cpu.is_null() {
+            return Err("emu can only be used while a hook runs".into());
+        }
+        // the pointer is only set by attach, which keeps the CPU mutably
+        // borrowed for as long as it is set, and calls don't nest
+        f(unsafe { &mut *cpu })
+    }
+}
+
+// keeps the emu pointing at a CPU until dropped
+struct Attached<'a> {
+    cpu: &'a Cell<*mut Chip8>
+}
+
+impl<'a> Drop for Attached<'a> {
+    fn drop(&mut self) {
+        self.cpu.set(ptr::null_mut());
+    }
+}
+
+fn register(x: i64) -> ScriptResult<usize> {
+    if (0..16).contains(&x) {
+        Ok(x as usize)
+    } else {
+        Err(format!("there is no register V{}", x).into())
+    }
+}
+
+fn keypad_key(key: i64) -> ScriptResult<usize> {
+    if (0..16).contains(&key) {
+        Ok(key as usize)
+    } else {
+        Err(format!("there is no key {}", key).into())
+    }
+}
+
+fn byte(value: i64) -> ScriptResult<u8> {
+    if (0..256).contains(&value) {
+        Ok(value as u8)
+    } else {
+        Err(format!("{} doesn't fit in a byte", value).into())
+    }
+}
+
+fn ram_address(cpu: &Chip8, address: i64) -> ScriptResult<usize> {
+    if address >= 0 && (address as usize) < cpu.ram.len() {
+        Ok(address as usize)
+    } else {
+        Err(format!("address {:#x} is outside of ram", address).into())
+    }
+}
+
+/// Runs the script's hooks for the start of a frame, if there is a script.
+/// A failing script is stopped with a warning instead of taking the
+/// emulator down with it, and the CPU stops tracing for it.
+pub fn before_frame(script: &mut Option<Script>, cpu: &mut Chip8) {
+    if let Some(Err(why)) = script.as_mut().map(|script| script.before_frame(cpu)) {
+        stop(script, cpu, &why);
+    }
+}
+
+/// Same as `before_frame`, for the end of a frame. Returns false once the
+/// script has quit.
+pub fn after_frame(script: &mut Option<Script>, cpu: &mut Chip8) -> bool {
+    if let Some(Err(why)) = script.as_mut().map(|script| script.after_frame(cpu)) {
+        stop(script, cpu, &why);
+    }
+    !script.as_ref().is_some_and(|script| script.quit())
+}
+
+fn stop(script: &mut Option<Script>, cpu: &mut Chip8, why: &str) {
+    println!("WARNING: script stopped: {}", why);
+    *script = None;
+    cpu.set_trace(false, false);
+}
+
+pub struct Script {
+    engine: Engine,
+    ast: AST,
+    emu: Emu,
+    // the script's `this`
+    state: Dynamic,
+    on_frame: bool,
+    on_instruction: bool,
+    on_write: bool,
+    on_key: bool,
+    keys: u16
+}
+
+impl Script {
+    pub fn load(path: &str) -> Result<Self, String> {
+        let source = fs::read_to_string(path)
+            .map_err(|why| format!("couldn't read {}: {}", path, why))?;
+        Script::new(&source).map_err(|why| format!("{}: {}", path, why))
+    }
+
+    /// Compiles a script and runs its top level
+    pub fn new(source: &str) -> Result<Self, String> {
+        let mut engine = Engine::new();
+        Emu::register(&mut engine);
+        let ast = engine.compile(source).map_err(|why| why.to_string())?;
+
+        let hook = |name: &str, params: usize| {
+            ast.iter_functions().any(|f| f.name == name && f.params.len() == params)
+        };
+        let (on_frame, on_instruction) = (hook("on_frame", 1), hook("on_instruction", 3));
+        let (on_write, on_key) = (hook("on_write", 3), hook("on_key", 3));
+
+        engine.run_ast(&ast).map_err(|why| why.to_string())?;
+        Ok(Script {
+            engine,
+            ast,
+            emu: Emu {
+                cpu: Rc::new(Cell::new(ptr::null_mut())),
+                frame: Rc::new(Cell::new(0)),
+                quit: Rc::new(Cell::new(false))
+            },
+            state: Dynamic::from_map(Map::new()),
+            on_frame,
+            on_instruction,
+            on_write,
+            on_key,
+            keys: 0
+        })
+    }
+
+    /// Calls `on_key` for the keys that changed since the last frame and
+    /// starts tracing what `on_instruction` and `on_write` need. Runs
+    /// before `cpu.run_frame()`.
+    pub fn before_frame(&mut self, cpu: &mut Chip8) -> Result<(), String> {
+        cpu.set_trace(self.on_instruction, self.on_write);
+        let (old_keys, keys) = (self.keys, cpu.keypad.state());
+        self.keys = keys;
+        if !self.on_key {
+            return Ok(());
+        }
+
+        let emu = self.emu.clone();
+        let _attached = emu.attach(cpu);
+        for key in 0..16 {
+            let pressed = keys & (1 << key) != 0;
+            if pressed != (old_keys & (1 << key) != 0) {
+                self.call("on_key", (key as i64, pressed))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Calls `on_instruction` and `on_write` for what ran during the frame,
+    /// in order, and then `on_frame`. Runs after `cpu.run_frame()`.
+    pub fn after_frame(&mut self, cpu: &mut Chip8) -> Result<(), String> {
+        let trace = cpu.take_trace();
+        self.emu.frame.set(self.emu.frame.get() + 1);
+
+        let emu = self.emu.clone();
+        let _attached = emu.attach(cpu);
+        for event in trace {
+            match event {
+                Trace::Instruction { pc, opcode } => {
+                    self.call("on_instruction", (pc as i64, opcode as i64))?;
+                },
+                Trace::Write { address, value } => {
+                    self.call("on_write", (address as i64, value as i64))?;
+                }
+            }
+        }
+        if self.on_frame {
+            self.call("on_frame", ())?;
+        }
+        Ok(())
+    }
+
+    /// Whether the script called `emu.quit()`
+    pub fn quit(&self) -> bool {
+        self.emu.quit.get()
+    }
+
+    fn call<A: FuncArgs>(&mut self, name: &str, args: A) -> Result<(), String> {
+        let mut all_args = vec![Dynamic::from(self.emu.clone())];
+        args.parse(&mut all_args);
+
+        let options = CallFnOptions::new().eval_ast(false).bind_this_ptr(&mut self.state);
+        self.engine.call_fn_with_options::<Dynamic>(options, &mut Scope::new(), &self.ast, name, all_args)
+            .map(|_| ())
+            .map_err(|why| format!("{}: {}", name, why))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run_frame(script: &mut Script, cpu: &mut Chip8) -> Result<(), String> {
+        script.before_frame(cpu)?;
+        cpu.run_frame();
+        script.after_frame(cpu)
+    }
+
+    #[test]
+    fn test_hooks() {
+        let mut script = Script::new(r#"
+            fn on_instruction(emu, pc, opcode) {
+                this.count = (this.count ?? 0) + 1;
+                emu.set_v(3, this.count);
+            }
+            fn on_write(emu, address, value) {
+                this.written = address;
+            }
+            fn on_key(emu, key, pressed) {
+                if pressed { emu.poke(0x300 + key, 1); }
+            }
+            fn on_frame(emu) {
+                emu.poke(0x310, this.written - 0x300);
+                emu.release(5);
+                if emu.frame == 2 { emu.quit(); }
+            }
+        "#).unwrap();
+
+        // V0 = 7, I = 0x300, BCD of V0 to I, then loop
+        let mut cpu = Chip8::with_seed(0);
+        cpu.load_rom(&[0x60, 0x07, 0xA3, 0x00, 0xF0, 0x33, 0x12, 0x06]);
+        cpu.keypad.press(5);
+        run_frame(&mut script, &mut cpu).unwrap();
+
+        assert_eq!(cpu.v(3), 8);
+        assert_eq!(cpu.ram[0x305], 1);
+        assert_eq!(cpu.ram[0x310], 2);
+        assert!(!cpu.keypad.is_pressed(5));
+        assert!(!script.quit());
+
+        run_frame(&mut script, &mut cpu).unwrap();
+        assert_eq!(cpu.v(3), 16);
+        assert!(script.quit());
+    }
+
+    #[test]
+    fn test_errors() {
+        assert!(Script::new("fn on_frame(emu) {").is_err());
+        assert!(Script::new("throw \"setup failed\";").is_err());
+
+        let mut script = Script::new("fn on_frame(emu) { emu.poke(0x1000, 1); }").unwrap();
+        let mut cpu = Chip8::with_seed(0);
+        assert!(run_frame(&mut script, &mut cpu).is_err());
+
+        let mut script = Script::new("fn on_frame(emu) { emu.set_v(0, 256); }").unwrap();
+        assert!(run_frame(&mut script, &mut cpu).is_err());
+    }
+
+    #[test]
+    fn test_stopped_script() {
+        let mut script = Some(Script::new("fn on_write(emu, address, value) { throw \"oops\"; }").unwrap());
+        let mut cpu = Chip8::with_seed(0);
+        // V0 = 7, I = 0x300, BCD of V0 to I, over and over
+        cpu.load_rom(&[0x60, 0x07, 0xA3, 0x00, 0xF0, 0x33, 0x12, 0x00]);
+        before_frame(&mut script, &mut cpu);
+        cpu.run_frame();
+        assert!(after_frame(&mut script, &mut cpu));
+        assert!(script.is_none());
+
+        // with the script gone nothing is traced any more
+        cpu.run_frame();
+        assert!(cpu.take_trace().is_empty());
+    }
+}
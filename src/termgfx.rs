@@ -0,0 +1,183 @@
+use std::fmt::Write;
+
+use display::Display;
+use palette::Palette;
+use screenshot;
+
+/// How the terminal frontend draws the display
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Graphics {
+    /// Half block characters, two pixels per cell
+    Blocks,
+    /// DEC Sixel images, supported by xterm, mlterm, foot, WezTerm and others
+    Sixel,
+    /// The kitty graphics protocol, also supported by WezTerm and Konsole
+    Kitty
+}
+
+impl Graphics {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "blocks" => Some(Graphics::Blocks),
+            "sixel" => Some(Graphics::Sixel),
+            "kitty" => Some(Graphics::Kitty),
+            _ => None
+        }
+    }
+}
+
+/// Encodes the display as a sixel image with every pixel a `scale` by
+/// `scale` square, the colours taken from the palette
+pub fn sixel(display: &Display, palette: &Palette, scale: usize) -> String {
+    let width = display.width() * scale;
+    let height = display.height() * scale;
+    let pixels = display.pixels();
+    let pixel = |x: usize, y: usize| pixels[y / scale][x / scale] as usize;
+
+    // 1:1 aspect ratio and the image size, then the colour registers in
+    // percentages
+    let mut out = String::new();
+    let _ = write!(out, "\x1bP0;1q\"1;1;{};{}", width, height);
+    for (register, color) in palette.colors.iter().enumerate() {
+        let [r, g, b] = color.map(|channel| channel as usize * 100 / 255);
+        let _ = write!(out, "#{};2;{};{};{}", register, r, g, b);
+    }
+
+    // six rows at a time, one pass over the band for each colour in it
+    let mut columns = vec![0u8; width];
+    for band in (0..height).step_by(6) {
+        let rows = band..(band + 6).min(height);
+        let mut first = true;
+        for register in 0..palette.colors.len() {
+            let mut used = false;
+            for (x, column) in columns.iter_mut().enumerate() {
+                *column = rows.clone()
+                    .filter(|&y| pixel(x, y) == register)
+                    .fold(0, |bits, y| bits | 1 << (y - band));
+                used |= *column != 0;
+            }
+            if !used {
+                continue;
+            }
+
+            if !first {
+                out.push('$');
+            }
+            first = false;
+            let _ = write!(out, "#{}", register);
+            push_runs(&mut out, &columns);
+        }
+        out.push('-');
+    }
+
+    out.push_str("\x1b\\");
+    out
+}
+
+// writes sixels, runs of more than three with a repeat count
+fn push_runs(out: &mut String, columns: &[u8]) {
+    let mut x = 0;
+    while x < columns.len() {
+        let bits = columns[x];
+        let run = columns[x..].iter().take_while(|&&other| other == bits).count();
+        let sixel = (63 + bits) as char;
+        if run > 3 {
+            let _ = write!(out, "!{}{}", run, sixel);
+        } else {
+            for _ in 0..run {
+                out.push(sixel);
+            }
+        }
+        x += run;
+    }
+}
+
+// the protocol limits the payload of each escape sequence
+const KITTY_CHUNK: usize = 4096;
+
+/// Encodes the display as a PNG sent with the kitty graphics protocol.
+/// Image and placement ids are fixed so each frame replaces the last.
+pub fn kitty(display: &Display, palette: &Palette, scale: usize) -> String {
+    let (rgba, width, height) = screenshot::render(display, palette, scale);
+    let mut png = Vec::new();
+    screenshot::write_png(&mut png, &rgba, width, height)
+        .expect("encoding to memory can't fail");
+    let payload = base64(&png);
+
+    let mut out = String::new();
+    let chunks: Vec<&[u8]> = payload.as_bytes().chunks(KITTY_CHUNK).collect();
+    for (i, chunk) in chunks.iter().enumerate() {
+        let more = (i + 1 < chunks.len()) as u8;
+        if i == 0 {
+            let _ = write!(out, "\x1b_Ga=T,f=100,i=1,p=1,q=2,C=1,m={};", more);
+        } else {
+            let _ = write!(out, "\x1b_Gm={};", more);
+        }
+        out.push_str(std::str::from_utf8(chunk).unwrap());
+        out.push_str("\x1b\\");
+    }
+    out
+}
+
+/// Removes the images sent with `kitty`
+pub const KITTY_DELETE: &str = "\x1b_Ga=d,d=I,i=1,q=2\x1b\\";
+
+const BASE64: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// Standard base64 with padding
+pub fn base64(bytes: &[u8]) -> String {
+    let mut out = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for group in bytes.chunks(3) {
+        let n = (group[0] as u32) << 16
+            | (*group.get(1).unwrap_or(&0) as u32) << 8
+            | *group.get(2).unwrap_or(&0) as u32;
+        for i in 0..4 {
+            if i <= group.len() {
+                out.push(BASE64[(n >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_base64() {
+        assert_eq!(base64(b""), "");
+        assert_eq!(base64(b"f"), "Zg==");
+        assert_eq!(base64(b"fo"), "Zm8=");
+        assert_eq!(base64(b"foo"), "Zm9v");
+        assert_eq!(base64(b"foobar"), "Zm9vYmFy");
+    }
+
+    #[test]
+    fn test_sixel() {
+        let mut display = Display::new();
+        display.draw(0, 0, 1, &[0x80]);
+        let out = sixel(&display, &Palette::default(), 1);
+
+        assert!(out.starts_with("\x1bP0;1q\"1;1;64;32#0;2;"));
+        assert!(out.ends_with("\x1b\\"));
+        // 32 rows make six bands
+        assert_eq!(out.matches('-').count(), 6);
+        // the first band has the lit pixel in its top row and the
+        // background everywhere else
+        assert!(out.contains("#0}!63~$#1@!63?-"));
+    }
+
+    #[test]
+    fn test_kitty() {
+        let out = kitty(&Display::new(), &Palette::default(), 8);
+
+        assert!(out.starts_with("\x1b_Ga=T,f=100,i=1,p=1,q=2,C=1,m="));
+        assert!(out.ends_with("\x1b\\"));
+        // the last chunk says there are no more
+        let last = out.rfind("\x1b_G").unwrap();
+        assert!(out[last..].contains("m=0;"));
+    }
+}
@@ -0,0 +1,288 @@
+use std::fmt::Write as FmtWrite;
+use std::io::{self, Write};
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+
+use crossterm::{cursor, execute, terminal};
+use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers,
+                       KeyboardEnhancementFlags, PopKeyboardEnhancementFlags,
+                       PushKeyboardEnhancementFlags};
+use sdl2::keyboard::Keycode;
+
+use cpu::Chip8;
+use display::Display;
+use input::Input;
+use keymap::KeyMap;
+use keypad::Keypad;
+use movie::Movie;
+use options::Options;
+use palette::Palette;
+use termgfx::{self, Graphics};
+use script::{self, Script};
+use FRAME_DURATION;
+
+// most terminals only send key presses, so a key is let go once it hasn't
+// been seen for this many frames. long enough to bridge the gaps between
+// auto repeats, except the first one
+const HOLD_FRAMES: u8 = 9;
+
+/// Runs the emulator in the terminal until Esc or Ctrl-C is pressed. The
+/// display is drawn with half blocks, each character cell showing two
+/// pixels, or as an image for terminals with sixel or kitty graphics.
+pub fn run(options: &Options, cpu: &mut Chip8, keymap: &KeyMap, mut playback: Option<Movie>,
+           recording: &mut Option<Movie>) -> Result<(), String> {
+    let mut stdout = io::stdout();
+    terminal::enable_raw_mode().map_err(|why| format!("couldn't set up the terminal: {}", why))?;
+    // terminals that can report key releases need no timeout
+    let releases = terminal::supports_keyboard_enhancement().unwrap_or(false);
+    let mut result = execute!(stdout, terminal::EnterAlternateScreen, cursor::Hide,
+                              terminal::Clear(terminal::ClearType::All));
+    if releases && result.is_ok() {
+        result = execute!(stdout, PushKeyboardEnhancementFlags(
+            KeyboardEnhancementFlags::REPORT_EVENT_TYPES));
+    }
+
+    let result = result.and_then(|_| run_loop(&mut stdout, options, cpu, keymap, releases,
+                                              &mut playback, recording));
+
+    // put the terminal back however the loop ended
+    if options.graphics == Graphics::Kitty {
+        let _ = stdout.write_all(termgfx::KITTY_DELETE.as_bytes());
+    }
+    if releases {
+        let _ = execute!(stdout, PopKeyboardEnhancementFlags);
+    }
+    let _ = execute!(stdout, cursor::Show, terminal::LeaveAlternateScreen);
+    let _ = terminal::disable_raw_mode();
+    result.map_err(|why| format!("terminal error: {}", why))
+}
+
+fn run_loop(stdout: &mut io::Stdout, options: &Options, cpu: &mut Chip8, keymap: &KeyMap,
+            releases: bool, playback: &mut Option<Movie>,
+            recording: &mut Option<Movie>) -> io::Result<()> {
+    let mut held = HeldKeys::new(if releases { None } else { Some(HOLD_FRAMES) });
+    let mut input = Input::new();
+    let mut frame = 0;
+    let mut beeping = false;
+    let mut next_frame = Instant::now();
+    let mut redraw = true;
+    let mut script = match options.script_path {
+        Some(ref path) => {
+            Some(Script::load(path).map_err(io::Error::other)?)
+        },
+        None => None
+    };
+
+    loop {
+        let playing = playback.is_some();
+        while event::poll(Duration::from_secs(0))? {
+            match event::read()? {
+                Event::Key(KeyEvent {code: KeyCode::Esc, ..}) => return Ok(()),
+                Event::Key(KeyEvent {code: KeyCode::Char('c'), modifiers, ..})
+                    if modifiers.contains(KeyModifiers::CONTROL) => return Ok(()),
+                Event::Resize(..) => redraw = true,
+                _ if playing => {},
+                Event::Key(key_event) => {
+                    if let Some(key) = lookup(keymap, key_event.code) {
+                        match key_event.kind {
+                            KeyEventKind::Release => input.handle(|keys| held.release(key, keys)),
+                            _ => input.handle(|keys| held.press(key, keys))
+                        }
+                    }
+                },
+                _ => {}
+            }
+        }
+
+        let movie_keys = playback.as_ref().map(|movie| movie.frame(frame));
+        match movie_keys {
+            Some(Some(keys)) => cpu.keypad.set_state(keys),
+            Some(None) => {
+                *playback = None;
+                cpu.keypad.set_state(0);
+            },
+            None => {
+                held.tick(&mut input.keys);
+                if let Some(keys) = input.next_frame() {
+                    cpu.keypad.set_state(keys);
+                }
+            }
+        }
+        if let Some(ref mut movie) = *recording {
+            movie.record_frame(cpu.keypad.state());
+        }
+
+        script::before_frame(&mut script, cpu);
+        cpu.run_frame();
+        if !script::after_frame(&mut script, cpu) {
+            return Ok(());
+        }
+        frame += 1;
+
+        if cpu.display.take_changed() || redraw {
+            let screen = match options.graphics {
+                Graphics::Blocks => render(&cpu.display, &options.palette),
+                Graphics::Sixel => {
+                    format!("\x1b[H{}", termgfx::sixel(&cpu.display, &options.palette,
+                                                      options.graphics_scale))
+                },
+                Graphics::Kitty => {
+                    format!("\x1b[H{}", termgfx::kitty(&cpu.display, &options.palette,
+                                                      options.graphics_scale))
+                }
+            };
+            stdout.write_all(screen.as_bytes())?;
+            redraw = false;
+        }
+        // the bell is as close as a terminal gets to the beeper
+        let beep = cpu.should_beep();
+        if beep && !beeping {
+            stdout.write_all(b"\x07")?;
+        }
+        beeping = beep;
+        stdout.flush()?;
+
+        next_frame += FRAME_DURATION;
+        let now = Instant::now();
+        if next_frame > now {
+            sleep(next_frame - now);
+        } else {
+            next_frame = now;
+        }
+    }
+}
+
+// characters are looked up as the SDL keycodes they share their values
+// with, so key maps and presets work the same as in the window. bindings
+// by scancode have no equivalent in a terminal
+fn lookup(keymap: &KeyMap, code: KeyCode) -> Option<usize> {
+    match code {
+        KeyCode::Char(c) if c.is_ascii() => {
+            keymap.lookup(Keycode::from_i32(c.to_ascii_lowercase() as i32), None)
+        },
+        _ => None
+    }
+}
+
+/// Keeps keys pressed between key events, letting go of them after a
+/// number of frames if the terminal never says they were released
+struct HeldKeys {
+    timeout: Option<u8>,
+    frames_left: [u8; 16]
+}
+
+impl HeldKeys {
+    fn new(timeout: Option<u8>) -> Self {
+        HeldKeys {
+            timeout,
+            frames_left: [0; 16]
+        }
+    }
+
+    fn press(&mut self, key: usize, keypad: &mut Keypad) {
+        if !keypad.is_pressed(key) {
+            keypad.press(key);
+        }
+        self.frames_left[key] = self.timeout.unwrap_or(0);
+    }
+
+    fn release(&mut self, key: usize, keypad: &mut Keypad) {
+        if keypad.is_pressed(key) {
+            keypad.release(key);
+        }
+        self.frames_left[key] = 0;
+    }
+
+    /// Counts down a frame, releasing the keys that timed out
+    fn tick(&mut self, keypad: &mut Keypad) {
+        if self.timeout.is_none() {
+            return;
+        }
+        for key in 0..16 {
+            if self.frames_left[key] > 0 {
+                self.frames_left[key] -= 1;
+                if self.frames_left[key] == 0 && keypad.is_pressed(key) {
+                    keypad.release(key);
+                }
+            }
+        }
+    }
+}
+
+/// Draws the display from the top left of the terminal with upper half
+/// blocks, the top pixel as the foreground colour and the bottom one as
+/// the background. Colours are only sent when they change.
+pub fn render(display: &Display, palette: &Palette) -> String {
+    let pixels = display.pixels();
+    let mut screen = String::from("\x1b[H");
+
+    for pair in pixels.chunks(2) {
+        let mut foreground = None;
+        let mut background = None;
+        for x in 0..display.width() {
+            let top = pair[0][x] as usize;
+            let bottom = pair.get(1).map_or(0, |row| row[x] as usize);
+            if foreground != Some(top) {
+                let [r, g, b] = palette.colors[top];
+                let _ = write!(screen, "\x1b[38;2;{};{};{}m", r, g, b);
+                foreground = Some(top);
+            }
+            if background != Some(bottom) {
+                let [r, g, b] = palette.colors[bottom];
+                let _ = write!(screen, "\x1b[48;2;{};{};{}m", r, g, b);
+                background = Some(bottom);
+            }
+            screen.push('\u{2580}');
+        }
+        screen.push_str("\x1b[0m\r\n");
+    }
+    screen
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render() {
+        let mut display = Display::new();
+        display.draw(0, 1, 1, &[0x80]);
+        let palette = Palette::default();
+        let screen = render(&display, &palette);
+
+        assert_eq!(screen.matches("\r\n").count(), 16);
+        assert_eq!(screen.matches('\u{2580}').count(), 64 * 16);
+
+        // the first cell has the lit pixel at the bottom
+        let [br, bg, bb] = palette.background();
+        let [fr, fg, fb] = palette.foreground();
+        let first_cell = format!("\x1b[H\x1b[38;2;{};{};{}m\x1b[48;2;{};{};{}m\u{2580}\x1b[48;2;{};{};{}m",
+                                 br, bg, bb, fr, fg, fb, br, bg, bb);
+        assert!(screen.starts_with(&first_cell));
+    }
+
+    #[test]
+    fn test_held_keys() {
+        let mut keypad = Keypad::new();
+        let mut held = HeldKeys::new(Some(2));
+
+        held.press(5, &mut keypad);
+        held.tick(&mut keypad);
+        assert!(keypad.is_pressed(5));
+        // a repeat keeps the key down
+        held.press(5, &mut keypad);
+        held.tick(&mut keypad);
+        assert!(keypad.is_pressed(5));
+        held.tick(&mut keypad);
+        assert!(!keypad.is_pressed(5));
+
+        // without a timeout keys stay down until released
+        let mut held = HeldKeys::new(None);
+        held.press(5, &mut keypad);
+        held.tick(&mut keypad);
+        held.tick(&mut keypad);
+        assert!(keypad.is_pressed(5));
+        held.release(5, &mut keypad);
+        assert!(!keypad.is_pressed(5));
+    }
+}
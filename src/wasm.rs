@@ -0,0 +1,111 @@
+//! JavaScript API for the browser build. Build it with
+//!
+//! ```text
+//! cargo rustc --lib --release --target wasm32-unknown-unknown --crate-type cdylib
+//! wasm-bindgen --target web --out-dir web/pkg target/wasm32-unknown-unknown/release/chip8.wasm
+//! ```
+//!
+//! and serve the `web` directory.
+
+use wasm_bindgen::prelude::*;
+
+use cpu::{self, Chip8};
+use palette::Palette;
+
+/// A chip8 driven one frame at a time by the page
+#[wasm_bindgen]
+pub struct Emulator {
+    cpu: Chip8,
+    seed: u32,
+    palette: Palette
+}
+
+#[wasm_bindgen]
+impl Emulator {
+    /// There is no OS random source in the browser, so the page passes a
+    /// seed for Cxkk, e.g. from `Math.random`
+    #[wasm_bindgen(constructor)]
+    pub fn new(seed: u32) -> Emulator {
+        Emulator {
+            cpu: Chip8::with_seed(seed as u64),
+            seed,
+            palette: Palette::default()
+        }
+    }
+
+    /// Resets the machine and loads a program
+    pub fn load_rom(&mut self, rom: &[u8]) -> Result<(), JsValue> {
+        if rom.len() > cpu::MAX_ROM_SIZE {
+            return Err(JsValue::from_str(&format!("ROM is {} bytes, at most {} fit in memory",
+                                                  rom.len(), cpu::MAX_ROM_SIZE)));
+        }
+        self.cpu = Chip8::with_seed(self.seed as u64);
+        self.cpu.load_rom(rom);
+        Ok(())
+    }
+
+    /// Runs one 60 hz frame
+    pub fn run_frame(&mut self) {
+        self.cpu.run_frame();
+    }
+
+    pub fn width(&self) -> usize {
+        self.cpu.display.width()
+    }
+
+    pub fn height(&self) -> usize {
+        self.cpu.display.height()
+    }
+
+    /// One byte per pixel, 1 if it is lit, row by row
+    pub fn pixels(&self) -> Vec<u8> {
+        self.cpu.display.pixels().iter()
+            .flat_map(|row| row.iter().map(|&pixel| pixel as u8))
+            .collect()
+    }
+
+    /// The display coloured with the palette as RGBA bytes, ready for an
+    /// `ImageData`
+    pub fn framebuffer(&self) -> Vec<u8> {
+        let mut rgba = Vec::with_capacity(self.width() * self.height() * 4);
+        for row in self.cpu.display.pixels().iter() {
+            for &pixel in row.iter() {
+                rgba.extend_from_slice(&self.palette.rgba(pixel as usize));
+            }
+        }
+        rgba
+    }
+
+    /// Whether the display changed since the last call
+    pub fn take_changed(&mut self) -> bool {
+        self.cpu.display.take_changed()
+    }
+
+    /// Presses or releases one of the 16 keys
+    pub fn set_key(&mut self, key: usize, down: bool) -> Result<(), JsValue> {
+        if key > 0xF {
+            return Err(JsValue::from_str(&format!("there is no key {}", key)));
+        }
+        if down && !self.cpu.keypad.is_pressed(key) {
+            self.cpu.keypad.press(key);
+        } else if !down && self.cpu.keypad.is_pressed(key) {
+            self.cpu.keypad.release(key);
+        }
+        Ok(())
+    }
+
+    /// Sets all keys from a bitmask, bit n being key n
+    pub fn set_keys(&mut self, keys: u16) {
+        self.cpu.keypad.set_state(keys);
+    }
+
+    /// Whether the beeper should be sounding
+    pub fn beeping(&mut self) -> bool {
+        self.cpu.should_beep()
+    }
+
+    /// Takes a comma separated list of colours like `#000000,#ffa500`
+    pub fn set_colors(&mut self, list: &str) -> Result<(), JsValue> {
+        self.palette.set_colors(list).map_err(|why| JsValue::from_str(&why))
+    }
+}
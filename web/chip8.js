@@ -0,0 +1,79 @@
+import init, { Emulator } from './pkg/chip8.js';
+
+// host keys for 1 2 3 C / 4 5 6 D / 7 8 9 E / A 0 B F, like the qwerty preset
+const KEYS = {
+  Digit1: 0x1, Digit2: 0x2, Digit3: 0x3, Digit4: 0xC,
+  KeyQ: 0x4, KeyW: 0x5, KeyE: 0x6, KeyR: 0xD,
+  KeyA: 0x7, KeyS: 0x8, KeyD: 0x9, KeyF: 0xE,
+  KeyZ: 0xA, KeyX: 0x0, KeyC: 0xB, KeyV: 0xF
+};
+const FRAME_MS = 1000 / 60;
+
+await init();
+
+const canvas = document.getElementById('screen');
+const context = canvas.getContext('2d');
+let emulator = null;
+let audio = null;
+let oscillator = null;
+
+document.getElementById('rom').addEventListener('change', async (event) => {
+  const rom = new Uint8Array(await event.target.files[0].arrayBuffer());
+  emulator = new Emulator(Math.floor(Math.random() * 0x100000000));
+  try {
+    emulator.load_rom(rom);
+  } catch (why) {
+    alert(why);
+    emulator = null;
+  }
+  audio = audio || new AudioContext();
+});
+
+for (const type of ['keydown', 'keyup']) {
+  document.addEventListener(type, (event) => {
+    if (emulator && event.code in KEYS) {
+      emulator.set_key(KEYS[event.code], type === 'keydown');
+      event.preventDefault();
+    }
+  });
+}
+
+function beep(on) {
+  if (on && !oscillator) {
+    oscillator = audio.createOscillator();
+    oscillator.type = 'square';
+    oscillator.frequency.value = 221;
+    const gain = audio.createGain();
+    gain.gain.value = 0.05;
+    oscillator.connect(gain).connect(audio.destination);
+    oscillator.start();
+  } else if (!on && oscillator) {
+    oscillator.stop();
+    oscillator = null;
+  }
+}
+
+// emulated frames follow the clock, whatever the display's refresh rate
+let last = performance.now();
+let behind = 0;
+function tick(now) {
+  behind = Math.min(behind + now - last, 10 * FRAME_MS);
+  last = now;
+  if (emulator) {
+    while (behind >= FRAME_MS) {
+      emulator.run_frame();
+      behind -= FRAME_MS;
+    }
+    if (emulator.take_changed()) {
+      if (canvas.width !== emulator.width() || canvas.height !== emulator.height()) {
+        canvas.width = emulator.width();
+        canvas.height = emulator.height();
+      }
+      const pixels = new Uint8ClampedArray(emulator.framebuffer());
+      context.putImageData(new ImageData(pixels, canvas.width, canvas.height), 0, 0);
+    }
+    beep(emulator.beeping());
+  }
+  requestAnimationFrame(tick);
+}
+requestAnimationFrame(tick);